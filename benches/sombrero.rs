@@ -0,0 +1,56 @@
+use std::convert::Infallible;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use http::{Request, Response};
+use tower::{service_fn, Layer, ServiceExt};
+use tower_sombrero::{
+    headers::{ContentSecurityPolicy, CspSource},
+    Sombrero,
+};
+
+fn bench_config(c: &mut Criterion, name: &str, sombrero: &Sombrero) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let service = sombrero.layer(service_fn(|_: Request<()>| async {
+        Ok::<_, Infallible>(Response::new(()))
+    }));
+    c.bench_function(name, |b| {
+        b.iter_batched(
+            || service.clone(),
+            |service| runtime.block_on(service.oneshot(Request::new(()))).unwrap(),
+            BatchSize::SmallInput,
+        );
+    });
+}
+
+fn sombrero_service(c: &mut Criterion) {
+    bench_config(c, "default", &Sombrero::default());
+    let with_nonce = ContentSecurityPolicy::strict_default().script_src([CspSource::Nonce]);
+    bench_config(
+        c,
+        "nonce csp",
+        &Sombrero::default().content_security_policy(with_nonce),
+    );
+    bench_config(
+        c,
+        "no csp",
+        &Sombrero::default().remove_content_security_policy(),
+    );
+}
+
+fn csp_value(c: &mut Criterion) {
+    let small = ContentSecurityPolicy::new_empty().default_src([CspSource::SelfOrigin]);
+    let hosts: Vec<_> = (0..64)
+        .map(|i| CspSource::Host(format!("https://cdn{i}.example.com").into()))
+        .collect();
+    let large = ContentSecurityPolicy::strict_default()
+        .script_src(hosts.clone())
+        .connect_src(hosts);
+    let nonce = "0123456789abcdefghijklmnopqrstuv";
+    c.bench_function("csp value small", |b| b.iter(|| small.value(nonce)));
+    c.bench_function("csp value large", |b| b.iter(|| large.value(nonce)));
+}
+
+criterion_group!(benches, sombrero_service, csp_value);
+criterion_main!(benches);
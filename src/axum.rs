@@ -8,8 +8,44 @@ use axum_core::{
     response::{IntoResponse, Response},
 };
 use http::{request::Parts, StatusCode};
+#[cfg(feature = "axum-routing")]
+use {
+    crate::{PreparedSombrero, Sombrero},
+    axum::middleware::Next,
+    axum_core::extract::Request,
+    futures_util::future::BoxFuture,
+};
+
+use crate::{csp::CspNonce, SombreroModifier};
 
-use crate::csp::CspNonce;
+#[cfg(feature = "axum-routing")]
+impl Sombrero {
+    /// This config as a function for [`axum::middleware::from_fn`], behaving exactly like the
+    /// [`Layer`](tower_layer::Layer), for stacks built out of function middleware.
+    ///
+    /// ```rust
+    /// # use axum::{middleware::from_fn, routing::get, Router};
+    /// # use tower_sombrero::Sombrero;
+    /// let app: Router = Router::new()
+    ///     .route("/", get(|| async { "hello" }))
+    ///     .layer(from_fn(Sombrero::default().into_axum_fn_middleware()));
+    /// ```
+    pub fn into_axum_fn_middleware(
+        self,
+    ) -> impl Fn(Request, Next) -> BoxFuture<'static, Response> + Clone + Send + Sync + 'static
+    {
+        let prepared = PreparedSombrero::new(self);
+        move |request, mut next| {
+            let future = prepared.call_service(&mut next, request);
+            Box::pin(async move {
+                match future.await {
+                    Ok(response) => response,
+                    Err(infallible) => match infallible {},
+                }
+            })
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct NonceNotFoundError;
@@ -35,3 +71,28 @@ impl<S: Sync> FromRequestParts<S> for CspNonce {
         parts.extensions.get().cloned().ok_or(NonceNotFoundError)
     }
 }
+
+#[derive(Debug)]
+pub struct ModifierNotFoundError;
+
+impl Display for ModifierNotFoundError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Sombrero modifier not found in extensions!")
+    }
+}
+
+impl Error for ModifierNotFoundError {}
+
+impl IntoResponse for ModifierNotFoundError {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
+
+impl<S: Sync> FromRequestParts<S> for SombreroModifier {
+    type Rejection = ModifierNotFoundError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get().cloned().ok_or(ModifierNotFoundError)
+    }
+}
@@ -0,0 +1,132 @@
+use std::task::{Context, Poll};
+
+use axum::body::Body;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures_util::future::BoxFuture;
+use http::{header::CONTENT_TYPE, Request, Response, StatusCode};
+use scraper::{Html, Selector};
+use sha2::{Digest, Sha256};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{csp::CspHashes, headers::CspHashAlgorithm, Sombrero, SombreroService};
+
+/// [`Sombrero`], but the `sha256` hashes of inline `<script>`s in HTML responses are added to
+/// `script-src`, so templates can include trusted inline scripts without a nonce.
+///
+/// Scripts with a `nonce` or `src` attribute are left alone. Only responses with a
+/// `text/html` content type are read, and their whole body is buffered to do so, which
+/// means streamed HTML is only sent once it is complete.
+///
+/// Every inline script in the response is trusted. Never use this on pages that could
+/// contain injected markup, as injected scripts would be hashed and allowed too.
+#[derive(Debug, Clone)]
+pub struct HashingCspMiddleware {
+    sombrero: Sombrero,
+}
+
+impl HashingCspMiddleware {
+    pub const fn new(sombrero: Sombrero) -> Self {
+        Self { sombrero }
+    }
+}
+
+impl<S> Layer<S> for HashingCspMiddleware {
+    type Service = SombreroService<InlineScriptHashService<S>>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        self.sombrero.layer(InlineScriptHashService { inner })
+    }
+}
+
+/// Adds the hashes of inline scripts in HTML responses to the request's [`CspHashes`].
+/// Used by [`HashingCspMiddleware`].
+#[derive(Debug, Clone)]
+pub struct InlineScriptHashService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for InlineScriptHashService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Future: Send + 'static,
+    S::Error: 'static,
+{
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+    type Response = Response<Body>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let hashes = request.extensions().get::<CspHashes>().cloned();
+        let future = self.inner.call(request);
+        Box::pin(async move {
+            let response = future.await?;
+            let Some(hashes) = hashes else {
+                return Ok(response);
+            };
+            if !is_html(&response) {
+                return Ok(response);
+            }
+            let (parts, body) = response.into_parts();
+            let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+                Ok(bytes) => bytes,
+                Err(error) => {
+                    tracing::error!(
+                        ?error,
+                        "failed to read HTML body to hash its inline scripts"
+                    );
+                    let mut response = Response::new(Body::empty());
+                    *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                    return Ok(response);
+                }
+            };
+            for digest in inline_script_hashes(&String::from_utf8_lossy(&bytes)) {
+                hashes.push(CspHashAlgorithm::Sha256, digest);
+            }
+            Ok(Response::from_parts(parts, Body::from(bytes)))
+        })
+    }
+}
+
+fn is_html(response: &Response<Body>) -> bool {
+    response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.trim_start().starts_with("text/html"))
+}
+
+/// The base64 `sha256` digests of the inline scripts in `html` without a nonce.
+fn inline_script_hashes(html: &str) -> Vec<String> {
+    let document = Html::parse_document(html);
+    let scripts = Selector::parse("script:not([nonce]):not([src])").expect("valid selector");
+    document
+        .select(&scripts)
+        .map(|script| {
+            let content: String = script.text().collect();
+            STANDARD.encode(Sha256::digest(content.as_bytes()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+#[test]
+fn hashes_only_inline_scripts_without_nonce() {
+    let html = r#"<!doctype html><html><head>
+        <script>alert(1)</script>
+        <script nonce="abc">alert(2)</script>
+        <script src="/app.js"></script>
+        </head><body><script>console.log("hi")</script></body></html>"#;
+    let hashes = inline_script_hashes(html);
+    assert_eq!(
+        hashes,
+        [
+            STANDARD.encode(Sha256::digest(b"alert(1)")),
+            STANDARD.encode(Sha256::digest(br#"console.log("hi")"#)),
+        ]
+    );
+}
@@ -0,0 +1,123 @@
+use std::sync::{Arc, OnceLock};
+
+use tower_layer::Layer;
+
+use crate::{PreparedSombrero, Sombrero, SombreroService};
+
+/// A lazily-initialized [`Sombrero`], suitable for a `static`.
+///
+/// ```rust
+/// # use tower_sombrero::SombreroCell;
+/// static SOMBRERO: SombreroCell = SombreroCell::new();
+/// ```
+#[derive(Debug, Default)]
+pub struct SombreroCell(OnceLock<Sombrero>);
+
+impl SombreroCell {
+    pub const fn new() -> Self {
+        Self(OnceLock::new())
+    }
+
+    /// Get the contained [`Sombrero`], initializing it with `f` if it has not been set yet.
+    pub fn get_or_init(&self, f: impl FnOnce() -> Sombrero) -> &Sombrero {
+        self.0.get_or_init(f)
+    }
+
+    /// Get the contained [`Sombrero`], if it has been initialized.
+    pub fn get(&self) -> Option<&Sombrero> {
+        self.0.get()
+    }
+
+    /// Initialize the cell. If it was already initialized, `sombrero` is handed back.
+    #[allow(clippy::result_large_err)] // mirrors `OnceLock::set`
+    pub fn set(&self, sombrero: Sombrero) -> Result<(), Sombrero> {
+        self.0.set(sombrero)
+    }
+}
+
+/// Uses [`Sombrero::default`] if the cell has not been initialized yet. `&SombreroCell` is also
+/// a layer, so a `static` cell can be used directly.
+impl<S> Layer<S> for SombreroCell {
+    type Service = SombreroService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        self.get_or_init(Sombrero::default).layer(inner)
+    }
+}
+
+/// A [`Sombrero`] for a `static`, built by `init` on first use and prepared only once.
+///
+/// Unlike [`SombreroCell`], every [`Layer::layer`] call shares the same prepared headers, and
+/// the config is always known up front. `&SombreroStatic` is also a layer. See
+/// [`lazy_sombrero!`](crate::lazy_sombrero) for declaring one.
+///
+/// ```rust
+/// # use tower_sombrero::{headers::XFrameOptions, Sombrero, SombreroStatic};
+/// static SOMBRERO: SombreroStatic =
+///     SombreroStatic::with_init(|| Sombrero::default().x_frame_options(XFrameOptions::Deny));
+/// ```
+#[derive(Debug)]
+pub struct SombreroStatic {
+    init: fn() -> Sombrero,
+    prepared: OnceLock<Arc<PreparedSombrero>>,
+}
+
+impl SombreroStatic {
+    /// Uses [`Sombrero::default`].
+    pub const fn new() -> Self {
+        Self::with_init(Sombrero::default)
+    }
+
+    pub const fn with_init(init: fn() -> Sombrero) -> Self {
+        Self {
+            init,
+            prepared: OnceLock::new(),
+        }
+    }
+
+    /// The config, initializing it if this is the first use.
+    pub fn get(&self) -> &Sombrero {
+        &self.prepared().sombrero
+    }
+
+    fn prepared(&self) -> &Arc<PreparedSombrero> {
+        self.prepared
+            .get_or_init(|| PreparedSombrero::new((self.init)()))
+    }
+}
+
+impl Default for SombreroStatic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for SombreroStatic {
+    type Service = SombreroService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SombreroService {
+            prepared: Arc::clone(self.prepared()),
+            inner,
+        }
+    }
+}
+
+/// Declare a `static` [`SombreroStatic`], with [`Sombrero::default`] or the config returned by
+/// a function or closure.
+///
+/// ```rust
+/// # use tower_sombrero::{headers::XFrameOptions, lazy_sombrero, Sombrero};
+/// lazy_sombrero!(pub SOMBRERO);
+/// lazy_sombrero!(STRICT = || Sombrero::default().x_frame_options(XFrameOptions::Deny));
+/// assert!(SOMBRERO.get().csp().is_some());
+/// ```
+#[macro_export]
+macro_rules! lazy_sombrero {
+    ($vis:vis $name:ident) => {
+        $vis static $name: $crate::SombreroStatic = $crate::SombreroStatic::new();
+    };
+    ($vis:vis $name:ident = $init:expr) => {
+        $vis static $name: $crate::SombreroStatic = $crate::SombreroStatic::with_init($init);
+    };
+}
@@ -1,34 +1,311 @@
 use std::{
     fmt::{Display, Formatter},
-    sync::Arc,
+    sync::{Arc, Mutex, OnceLock, PoisonError},
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
+use base64::{engine::general_purpose::STANDARD, Engine};
 use futures_util::future::BoxFuture;
 use http::{
-    header::{CONTENT_SECURITY_POLICY, CONTENT_SECURITY_POLICY_REPORT_ONLY},
-    Request, Response,
+    header::{
+        InvalidHeaderName, CONTENT_SECURITY_POLICY, CONTENT_SECURITY_POLICY_REPORT_ONLY, ETAG,
+    },
+    HeaderName, HeaderValue, Request, Response,
 };
 use tower_layer::Layer;
 use tower_service::Service;
 
-use crate::{headers::ContentSecurityPolicy, middleware_add_raw_header};
+use crate::{
+    fail_closed,
+    headers::{ContentSecurityPolicy, CspHashAlgorithm, CspSource},
+    random_string, NONCE_LENGTH,
+};
 
 pub const BAD_CSP_MESSAGE: &str =
     "Failed to create CSP header. Did you pass an invalid header value into a custom string?";
 
-#[derive(Clone, Debug)]
-pub struct CspNonce(pub String);
+/// Sent in place of a policy that fails to serialize, such as one with an invalid `report-uri`.
+/// It blocks everything, so a broken config never leaves a page unprotected.
+pub(crate) const FALLBACK_CSP: HeaderValue =
+    HeaderValue::from_static("default-src 'none';sandbox;");
+
+/// The nonce of the current request.
+///
+/// [`Sombrero`](crate::Sombrero) puts one in the extensions of every request, but only generates
+/// it the first time it is read, by a handler or by a policy containing [`CspSource::Nonce`].
+/// Clones share the same nonce.
+#[derive(Clone)]
+pub struct CspNonce(Arc<LazyNonce>);
+
+struct LazyNonce {
+    value: OnceLock<String>,
+    seed: Option<NonceSeed>,
+    generator: Option<Arc<dyn NonceGenerator>>,
+}
+
+impl CspNonce {
+    pub fn new(nonce: impl Into<String>) -> Self {
+        Self(Arc::new(LazyNonce {
+            value: OnceLock::from(nonce.into()),
+            seed: None,
+            generator: None,
+        }))
+    }
+
+    /// A nonce generated on first use, from `seed` if there is one, then from `generator`,
+    /// and otherwise with [`random_string`].
+    pub(crate) fn lazy(
+        seed: Option<NonceSeed>,
+        generator: Option<Arc<dyn NonceGenerator>>,
+    ) -> Self {
+        Self(Arc::new(LazyNonce {
+            value: OnceLock::new(),
+            seed,
+            generator,
+        }))
+    }
+
+    /// The raw nonce.
+    pub fn value(&self) -> &str {
+        let lazy = &self.0;
+        lazy.value
+            .get_or_init(|| match (&lazy.seed, &lazy.generator) {
+                (Some(seed), _) => seed.nonce(),
+                (None, Some(generator)) => generator.generate(),
+                (None, None) => random_string(NONCE_LENGTH),
+            })
+    }
+
+    /// The raw nonce, like [`Self::value`].
+    pub fn as_str(&self) -> &str {
+        self.value()
+    }
+
+    /// `nonce="..."`, for a `<script>` tag.
+    pub fn script_attr(&self) -> String {
+        format!("nonce=\"{}\"", self.value())
+    }
+
+    /// `nonce="..."`, for a `<style>` or `<link rel="stylesheet">` tag.
+    pub fn style_attr(&self) -> String {
+        self.script_attr()
+    }
+}
+
+impl From<String> for CspNonce {
+    fn from(nonce: String) -> Self {
+        Self::new(nonce)
+    }
+}
+
+/// Shows the nonce only once it was generated, so formatting never generates one.
+impl std::fmt::Debug for CspNonce {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.0.value.get() {
+            Some(nonce) => f.debug_tuple("CspNonce").field(nonce).finish(),
+            None => f.write_str("CspNonce(..)"),
+        }
+    }
+}
+
+impl AsRef<str> for CspNonce {
+    fn as_ref(&self) -> &str {
+        self.value()
+    }
+}
 
 impl Display for CspNonce {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        Display::fmt(&self.0, f)
+        Display::fmt(self.value(), f)
+    }
+}
+
+/// Makes the nonce for each request. [`Sombrero`](crate::Sombrero) uses 32 random
+/// alphanumeric characters unless told otherwise with
+/// [`Sombrero::nonce_generator`](crate::Sombrero::nonce_generator).
+pub trait NonceGenerator: std::fmt::Debug + Send + Sync {
+    /// Make a nonce. It must only contain base64 characters.
+    fn generate(&self) -> String;
+}
+
+/// Predictable nonces: `prefix0`, `prefix1`, and so on, for tests and snapshots.
+///
+/// # Never use this in production
+///
+/// A nonce only protects anything if attackers can't guess it. These can be guessed trivially,
+/// so a policy using them is no better than `'unsafe-inline'`.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug, Default)]
+pub struct SequentialNonce {
+    prefix: String,
+    next: std::sync::atomic::AtomicU64,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl SequentialNonce {
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            next: std::sync::atomic::AtomicU64::new(0),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl NonceGenerator for SequentialNonce {
+    fn generate(&self) -> String {
+        let n = self.next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        format!("{}{n}", self.prefix)
+    }
+}
+
+/// Random bytes that every nonce of a request is derived from, so that layers generating
+/// nonces independently agree on one. Put in the request extensions by [`NonceSeedLayer`].
+///
+/// [`CspLayer`] and [`Sombrero`](crate::Sombrero) use [`Self::nonce`] when this is present,
+/// in place of generating a nonce, or of any [`NonceGenerator`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NonceSeed(pub [u8; 24]);
+
+impl NonceSeed {
+    pub fn random() -> Self {
+        Self(rand::random())
+    }
+
+    /// The nonce for this seed: its bytes, base64-encoded.
+    pub fn nonce(&self) -> String {
+        STANDARD.encode(self.0)
+    }
+}
+
+/// Doesn't show the seed, as it gives away the nonce.
+impl std::fmt::Debug for NonceSeed {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("NonceSeed(..)")
+    }
+}
+
+/// Adds a random [`NonceSeed`] to each request that doesn't have one yet.
+///
+/// Put this outside every layer generating nonces, so they all use the same one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NonceSeedLayer;
+
+impl<S> Layer<S> for NonceSeedLayer {
+    type Service = NonceSeedService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        NonceSeedService { inner }
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct NonceSeedService<S> {
+    inner: S,
+}
+
+impl<S, Body> Service<Request<Body>> for NonceSeedService<S>
+where
+    S: Service<Request<Body>>,
+{
+    type Error = S::Error;
+    type Future = S::Future;
+    type Response = S::Response;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<Body>) -> Self::Future {
+        if request.extensions().get::<NonceSeed>().is_none() {
+            request.extensions_mut().insert(NonceSeed::random());
+        }
+        self.inner.call(request)
+    }
+}
+
+/// Hashes of inline scripts rendered for this request.
+///
+/// [`Sombrero`](crate::Sombrero) puts one of these in the request extensions. Handlers can push
+/// hashes of the inline scripts they render, and they are appended to the `script-src` of the
+/// configured policies when the response comes back. If `script-src` is unset, it is seeded
+/// from `default-src` first, so adding hashes never loosens the policy.
+#[derive(Clone, Debug, Default)]
+pub struct CspHashes(Arc<Mutex<Vec<CspSource>>>);
+
+impl CspHashes {
+    pub fn push(&self, algorithm: CspHashAlgorithm, digest: impl Into<String>) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(CspSource::Hash(algorithm, digest.into()));
+    }
+
+    pub(crate) fn take(&self) -> Vec<CspSource> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+/// A deviation from the [OWASP CSP recommendations] found in an enforced policy.
+///
+/// [OWASP CSP recommendations]: https://cheatsheetseries.owasp.org/cheatsheets/Content_Security_Policy_Cheat_Sheet.html
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum OwaspCspWarning {
+    /// `script-src` (or `default-src`, if it is unset) allows `'unsafe-inline'`.
+    UnsafeInlineInEnforcedScriptSrc,
+    /// Neither `default-src` nor `script-src` is set, so scripts are unrestricted.
+    NoDefaultSrcOrScriptSrc,
+    /// `object-src` does not resolve to `'none'`.
+    MissingObjectSrcNone,
+    /// `base-uri` is not restricted to `'self'` or `'none'`. It does not fall back to `default-src`.
+    MissingBaseUriSelf,
+    /// `upgrade-insecure-requests` is not enabled.
+    MissingUpgradeInsecureRequests,
+}
+
+impl OwaspCspWarning {
+    pub(crate) fn check(csp: &ContentSecurityPolicy) -> Vec<Self> {
+        let mut warnings = Vec::new();
+        let script_src = if csp.script_src.is_empty() {
+            &csp.default_src
+        } else {
+            &csp.script_src
+        };
+        if script_src.contains(&CspSource::UnsafeInline) {
+            warnings.push(Self::UnsafeInlineInEnforcedScriptSrc);
+        }
+        if csp.default_src.is_empty() && csp.script_src.is_empty() {
+            warnings.push(Self::NoDefaultSrcOrScriptSrc);
+        }
+        let object_src = if csp.object_src.is_empty() {
+            &csp.default_src
+        } else {
+            &csp.object_src
+        };
+        if object_src.as_slice() != [CspSource::None] {
+            warnings.push(Self::MissingObjectSrcNone);
+        }
+        if csp.base_uri.as_slice() != [CspSource::SelfOrigin]
+            && csp.base_uri.as_slice() != [CspSource::None]
+        {
+            warnings.push(Self::MissingBaseUriSelf);
+        }
+        if !csp.upgrade_insecure_requests {
+            warnings.push(Self::MissingUpgradeInsecureRequests);
+        }
+        warnings
+    }
+}
+
+/// Adds a content security policy, and a [`CspNonce`] for it, to every response.
+///
+/// If a [`ContentSecurityPolicy`] is present in the request extensions (put there by an outer
+/// layer), it takes precedence over the configured one for that request. Only one nonce is
+/// generated either way.
 #[derive(Debug, Clone)]
 pub struct CspLayer {
-    report_only: bool,
+    header_name: HeaderName,
     csp: Arc<ContentSecurityPolicy>,
 }
 
@@ -50,7 +327,24 @@ impl CspLayer {
     }
 
     const fn new_internal(csp: Arc<ContentSecurityPolicy>, report_only: bool) -> Self {
-        Self { report_only, csp }
+        let header_name = if report_only {
+            CONTENT_SECURITY_POLICY_REPORT_ONLY
+        } else {
+            CONTENT_SECURITY_POLICY
+        };
+        Self { header_name, csp }
+    }
+
+    /// Send the policy under `name` instead of `Content-Security-Policy`
+    /// (or `Content-Security-Policy-Report-Only`).
+    ///
+    /// # Errors
+    /// If `name` is not a valid header name.
+    pub fn with_header_name(self, name: &str) -> Result<Self, InvalidHeaderName> {
+        Ok(Self {
+            header_name: HeaderName::try_from(name)?,
+            ..self
+        })
     }
 }
 
@@ -59,7 +353,7 @@ impl<S> Layer<S> for CspLayer {
 
     fn layer(&self, inner: S) -> Self::Service {
         CspService {
-            report_only: self.report_only,
+            header_name: self.header_name.clone(),
             csp: self.csp.clone(),
             inner,
         }
@@ -68,7 +362,7 @@ impl<S> Layer<S> for CspLayer {
 
 #[derive(Debug, Clone)]
 pub struct CspService<S> {
-    report_only: bool,
+    header_name: HeaderName,
     csp: Arc<ContentSecurityPolicy>,
     inner: S,
 }
@@ -89,21 +383,290 @@ where
     }
 
     fn call(&mut self, mut request: Request<Body>) -> Self::Future {
-        let nonce_string = crate::random_string(32);
-        request
-            .extensions_mut()
-            .insert(CspNonce(nonce_string.clone()));
+        let nonce_string = request
+            .extensions()
+            .get::<NonceSeed>()
+            .map_or_else(|| random_string(NONCE_LENGTH), NonceSeed::nonce);
+        let csp = request
+            .extensions()
+            .get::<ContentSecurityPolicy>()
+            .unwrap_or(&self.csp)
+            .value(&nonce_string);
+        request.extensions_mut().insert(CspNonce::new(nonce_string));
 
         let future = self.inner.call(request);
+        let header_name = self.header_name.clone();
+        Box::pin(async move {
+            let mut response = future.await?;
+            let csp = csp.unwrap_or_else(|error| {
+                fail_closed(&mut response, &error);
+                FALLBACK_CSP
+            });
+            response.headers_mut().insert(header_name, csp);
+            Ok(response)
+        })
+    }
+}
 
-        let csp = self.csp.value(&nonce_string).expect(BAD_CSP_MESSAGE);
+/// Makes `ETag`s unique per [`CspNonce`], so caches never revalidate a page with a stale nonce.
+///
+/// `ETag: "abc123"` becomes `ETag: "abc123-nonce-<nonce>"`. Responses without an `ETag`, and
+/// requests without a nonce, are left alone. This must be inside the layer providing the nonce.
+/// If responses shouldn't be cached at all, see [`Sombrero::no_cache_with_nonce`](crate::Sombrero::no_cache_with_nonce).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NonceCacheLayer;
 
-        let name = if self.report_only {
-            CONTENT_SECURITY_POLICY_REPORT_ONLY
-        } else {
-            CONTENT_SECURITY_POLICY
+impl<S> Layer<S> for NonceCacheLayer {
+    type Service = NonceCacheService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        NonceCacheService { inner }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NonceCacheService<S> {
+    inner: S,
+}
+
+impl<S, Body> Service<Request<Body>> for NonceCacheService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Future: Send + 'static,
+    S::Error: 'static,
+    Body: Send + 'static,
+{
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+    type Response = Response<Body>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let nonce = request.extensions().get::<CspNonce>().cloned();
+        let future = self.inner.call(request);
+        Box::pin(async move {
+            let mut response = future.await?;
+            if let Some(nonce) = nonce {
+                let etag = response
+                    .headers()
+                    .get(ETAG)
+                    .and_then(|etag| etag_with_nonce(etag, nonce.value()));
+                if let Some(etag) = etag {
+                    response.headers_mut().insert(ETAG, etag);
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+/// Rotates the nonce of long-lived responses, like server-sent event streams, on a schedule.
+///
+/// This adds a [`RotatingNonce`] to the request extensions, starting from the request's
+/// [`CspNonce`], so it must be inside the layer providing the nonce. The handler keeps it
+/// for the lifetime of the stream and calls [`RotatingNonce::sse_event`] between events.
+/// Once `interval` has passed, that rotates the nonce and returns an SSE event announcing it:
+///
+/// ```text
+/// event: nonce
+/// data: nonce=<new nonce>
+/// ```
+///
+/// # Limitations
+///
+/// Headers are sent once, so the `Content-Security-Policy` the browser enforces for the
+/// response never changes: HTTP/1.1 has no way to replace them, and trailers are not applied
+/// to CSP. A rotated nonce therefore doesn't allow new inline scripts in the page that opened
+/// the stream. It is a signal for clients to use the new nonce for content they fetch or
+/// render afterwards, and keeps a single nonce from being valid for hours. WebSocket handlers
+/// can send the same signal in their own framing using [`RotatingNonce::current`].
+/// Rotation is checked lazily when the nonce is read, so no timer or runtime is needed.
+#[derive(Debug, Clone)]
+pub struct TimedNonceRotationLayer {
+    interval: Duration,
+    generator: Option<Arc<dyn NonceGenerator>>,
+}
+
+impl TimedNonceRotationLayer {
+    pub const fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            generator: None,
+        }
+    }
+
+    /// Make rotated nonces with `generator`, instead of 32 random alphanumeric characters.
+    #[must_use]
+    pub fn generator(self, generator: impl NonceGenerator + 'static) -> Self {
+        Self {
+            generator: Some(Arc::new(generator)),
+            ..self
+        }
+    }
+}
+
+impl<S> Layer<S> for TimedNonceRotationLayer {
+    type Service = TimedNonceRotationService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TimedNonceRotationService {
+            layer: self.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TimedNonceRotationService<S> {
+    layer: TimedNonceRotationLayer,
+    inner: S,
+}
+
+impl<S, Body> Service<Request<Body>> for TimedNonceRotationService<S>
+where
+    S: Service<Request<Body>>,
+{
+    type Error = S::Error;
+    type Future = S::Future;
+    type Response = S::Response;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<Body>) -> Self::Future {
+        if let Some(nonce) = request.extensions().get::<CspNonce>().cloned() {
+            let rotating = RotatingNonce::new(
+                nonce,
+                self.layer.interval,
+                self.layer.generator.clone(),
+                Instant::now(),
+            );
+            request.extensions_mut().insert(rotating);
+        }
+        self.inner.call(request)
+    }
+}
+
+/// A nonce that is replaced every interval. See [`TimedNonceRotationLayer`].
+/// Clones share the same nonce.
+#[derive(Debug, Clone)]
+pub struct RotatingNonce {
+    state: Arc<Mutex<RotationState>>,
+    interval: Duration,
+    generator: Option<Arc<dyn NonceGenerator>>,
+}
+
+#[derive(Debug)]
+struct RotationState {
+    nonce: CspNonce,
+    rotated_at: Instant,
+    announced: bool,
+}
+
+impl RotatingNonce {
+    fn new(
+        nonce: CspNonce,
+        interval: Duration,
+        generator: Option<Arc<dyn NonceGenerator>>,
+        now: Instant,
+    ) -> Self {
+        let state = RotationState {
+            nonce,
+            rotated_at: now,
+            announced: true,
         };
+        Self {
+            state: Arc::new(Mutex::new(state)),
+            interval,
+            generator,
+        }
+    }
 
-        Box::pin(middleware_add_raw_header(name, csp, future))
+    /// The current nonce, rotating it first if it is due.
+    pub fn current(&self) -> CspNonce {
+        self.current_at(Instant::now())
     }
+
+    /// If the nonce rotated since the last call, the SSE event announcing the new one.
+    pub fn sse_event(&self) -> Option<String> {
+        self.sse_event_at(Instant::now())
+    }
+
+    fn current_at(&self, now: Instant) -> CspNonce {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        self.rotate_if_due(&mut state, now);
+        state.nonce.clone()
+    }
+
+    fn sse_event_at(&self, now: Instant) -> Option<String> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        self.rotate_if_due(&mut state, now);
+        if state.announced {
+            return None;
+        }
+        state.announced = true;
+        let nonce = state.nonce.clone();
+        drop(state);
+        Some(format!("event: nonce\ndata: nonce={nonce}\n\n"))
+    }
+
+    fn rotate_if_due(&self, state: &mut RotationState, now: Instant) {
+        if now.saturating_duration_since(state.rotated_at) < self.interval {
+            return;
+        }
+        let nonce = self.generator.as_ref().map_or_else(
+            || random_string(NONCE_LENGTH),
+            |generator| generator.generate(),
+        );
+        state.nonce = CspNonce::new(nonce);
+        state.rotated_at = now;
+        state.announced = false;
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn nonce_rotation_timing() {
+    let start = Instant::now();
+    let second = Duration::from_secs(1);
+    let rotating = RotatingNonce::new(
+        CspNonce::new("first"),
+        Duration::from_secs(30),
+        Some(Arc::new(SequentialNonce::new("rotated"))),
+        start,
+    );
+
+    assert_eq!(rotating.current_at(start + 29 * second).as_str(), "first");
+    assert_eq!(rotating.sse_event_at(start + 29 * second), None);
+
+    let event = rotating.sse_event_at(start + 30 * second);
+    assert_eq!(
+        event.as_deref(),
+        Some("event: nonce\ndata: nonce=rotated0\n\n")
+    );
+    // announced once, and the interval restarts from the rotation
+    assert_eq!(rotating.sse_event_at(start + 59 * second), None);
+    assert_eq!(
+        rotating.current_at(start + 59 * second).as_str(),
+        "rotated0"
+    );
+
+    // reading the nonce through a clone rotates it too, and the event still announces it
+    let shared = rotating.clone();
+    assert_eq!(shared.current_at(start + 61 * second).as_str(), "rotated1");
+    let event = rotating.sse_event_at(start + 62 * second);
+    assert_eq!(
+        event.as_deref(),
+        Some("event: nonce\ndata: nonce=rotated1\n\n")
+    );
+}
+
+fn etag_with_nonce(etag: &HeaderValue, nonce: &str) -> Option<HeaderValue> {
+    let etag = etag.to_str().ok()?;
+    let opaque = etag.strip_suffix('"')?;
+    HeaderValue::from_str(&format!("{opaque}-nonce-{nonce}\"")).ok()
 }
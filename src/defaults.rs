@@ -0,0 +1,44 @@
+use crate::{headers::ContentSecurityPolicy, Sombrero};
+
+/// A builder that starts from [`Sombrero::default`], for "the defaults, except…" configs.
+///
+/// There are three ways to make a [`Sombrero`]:
+///
+/// ```rust
+/// # use tower_sombrero::{headers::ContentSecurityPolicy, Sombrero};
+/// // Nothing enabled, add what you want
+/// let empty = Sombrero::new_empty();
+/// // The recommended defaults, unmodified
+/// let default = Sombrero::default();
+/// // The recommended defaults, with intentional changes
+/// let modified = Sombrero::defaults()
+///     .with_csp(ContentSecurityPolicy::strict_default().upgrade_insecure_requests(false))
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct SombreroDefaults(Sombrero);
+
+impl Sombrero {
+    /// Start a [`SombreroDefaults`] builder.
+    pub fn defaults() -> SombreroDefaults {
+        SombreroDefaults(Self::default())
+    }
+}
+
+impl SombreroDefaults {
+    /// Replace the default enforced content security policy.
+    pub fn with_csp(self, csp: ContentSecurityPolicy) -> Self {
+        Self(self.0.content_security_policy(csp))
+    }
+
+    /// Keep the other defaults, but don't send an enforced content security policy.
+    pub fn without_csp(self) -> Self {
+        Self(self.0.remove_content_security_policy())
+    }
+
+    #[must_use]
+    pub fn build(self) -> Sombrero {
+        self.0
+    }
+}
@@ -0,0 +1,64 @@
+use std::task::{Context, Poll};
+
+use futures_util::future::BoxFuture;
+use http::{Request, Response};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{Sombrero, SombreroService};
+
+/// [`Sombrero`], but errors from the inner service are turned into responses, which then get
+/// the security headers like any other. Created with [`Sombrero::fail_open`].
+///
+/// The inner error type must implement `Into<Response<Body>>`, usually through a
+/// `From<MyError> for Response<Body>` impl.
+#[derive(Debug, Clone)]
+pub struct FailOpenLayer {
+    sombrero: Sombrero,
+}
+
+impl Sombrero {
+    /// Turn errors from the inner service into responses carrying the security headers,
+    /// instead of passing them up without any headers. See [`FailOpenLayer`].
+    pub const fn fail_open(self) -> FailOpenLayer {
+        FailOpenLayer { sombrero: self }
+    }
+}
+
+impl<S> Layer<S> for FailOpenLayer {
+    type Service = SombreroService<ErrorResponseService<S>>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        self.sombrero.layer(ErrorResponseService { inner })
+    }
+}
+
+/// Converts the errors of `S` into responses. Used by [`FailOpenLayer`].
+///
+/// Errors from `poll_ready` mean the service can't take requests at all, rather than that one
+/// request failed, so they are passed through unchanged.
+#[derive(Debug, Clone)]
+pub struct ErrorResponseService<S> {
+    inner: S,
+}
+
+impl<S, Body> Service<Request<Body>> for ErrorResponseService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Future: Send + 'static,
+    S::Error: Into<Response<Body>> + 'static,
+    Body: Send + 'static,
+{
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+    type Response = Response<Body>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let future = self.inner.call(request);
+        Box::pin(async move { Ok(future.await.unwrap_or_else(Into::into)) })
+    }
+}
@@ -1,17 +1,23 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashSet};
 
 use http::{header::InvalidHeaderValue, HeaderValue};
 
 // https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Content-Security-Policy
 
+const UPGRADE_INSECURE_REQUESTS: &str = "upgrade-insecure-requests;";
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ContentSecurityPolicy {
     // fetch directives
     pub default_src: Vec<CspSource>,
     pub child_src: Vec<CspSource>,
     pub connect_src: Vec<CspSource>,
+    /// Removed from the spec and ignored by current browsers. Kept in case it returns.
+    pub fetch_src: Vec<CspSource>,
     pub font_src: Vec<CspSource>,
     pub frame_src: Vec<CspSource>,
+    /// Falls back to `frame-src`, then `default-src`.
+    pub fenced_frame_src: Vec<CspSource>,
     pub img_src: Vec<CspSource>,
     pub manifest_src: Vec<CspSource>,
     pub media_src: Vec<CspSource>,
@@ -29,8 +35,23 @@ pub struct ContentSecurityPolicy {
     // Navigation directives
     pub form_action: Vec<CspSource>,
     pub frame_ancestors: Vec<CspSource>,
+    // Trusted types
+    /// The Trusted Types policy names scripts may create, as [`CspSource::TrustedTypePolicy`],
+    /// [`CspSource::TrustedTypeStar`], [`CspSource::AllowDuplicates`] or
+    /// [`CspSource::TrustedTypeNone`] alone.
+    pub trusted_types: Vec<CspSource>,
     // Misc
     pub upgrade_insecure_requests: bool,
+    /// The reporting group violations are sent to. It must be declared in the
+    /// `Reporting-Endpoints` header, see [`Sombrero::with_reporting`](crate::Sombrero::with_reporting).
+    pub report_to: Option<String>,
+    /// The URI violation reports are posted to, absolute or relative to the page. Superseded
+    /// by `report-to`, but browsers without the Reporting API only report here, and the
+    /// others ignore it when `report-to` is set, so sending both is fine.
+    pub report_uri: Option<String>,
+    /// Serialize keyword sources first, then schemes, then hosts, then nonces and hashes,
+    /// instead of in insertion order.
+    pub normalize_source_order: bool,
 }
 
 impl ContentSecurityPolicy {
@@ -40,8 +61,10 @@ impl ContentSecurityPolicy {
             default_src: vec![],
             child_src: vec![],
             connect_src: vec![],
+            fetch_src: vec![],
             font_src: vec![],
             frame_src: vec![],
+            fenced_frame_src: vec![],
             img_src: vec![],
             manifest_src: vec![],
             media_src: vec![],
@@ -57,7 +80,11 @@ impl ContentSecurityPolicy {
             sandbox: vec![],
             form_action: vec![],
             frame_ancestors: vec![],
+            trusted_types: vec![],
             upgrade_insecure_requests: false,
+            report_to: None,
+            report_uri: None,
+            normalize_source_order: false,
         }
     }
 
@@ -88,31 +115,288 @@ impl ContentSecurityPolicy {
             ..Self::new_empty()
         }
     }
+
+    /// A `default-src 'none'` baseline that only allows same-origin scripts, styles, images and
+    /// `fetch`/WebSocket connections, for single-page apps backed by their own API.
+    ///
+    /// Anything not listed is blocked, so expect these to break:
+    /// - fonts, media, web workers, iframes and web app manifests, even same-origin ones
+    /// - inline scripts and styles, including `style` attributes, unless hashed
+    /// - `data:` and `blob:` images, and anything served from a CDN
+    /// - `<form>` submissions and `<base>` elements
+    /// - being framed by any page
+    pub fn locked_down() -> Self {
+        Self {
+            default_src: vec![CspSource::None],
+            script_src: vec![CspSource::SelfOrigin],
+            style_src: vec![CspSource::SelfOrigin],
+            img_src: vec![CspSource::SelfOrigin],
+            connect_src: vec![CspSource::SelfOrigin],
+            base_uri: vec![CspSource::None],
+            form_action: vec![CspSource::None],
+            frame_ancestors: vec![CspSource::None],
+            ..Self::new_empty()
+        }
+    }
+
+    /// [`Self::strict_default`], adjusted for SAML SSO pages.
+    ///
+    /// The only changes SAML needs are that `form-action` allows posting to the IdP
+    /// (or the assertion consumer service), and that `frame-ancestors` is `'none'`,
+    /// because login pages should never be embedded.
+    pub fn for_saml_sso(idp_url: &str) -> Self {
+        let mut csp = Self::strict_default();
+        csp.form_action
+            .push(CspSource::Host(idp_url.to_string().into()));
+        csp.frame_ancestors = vec![CspSource::None];
+        csp
+    }
+
+    /// A realistic policy for a single-page app, as a reference for building your own:
+    /// scripts by nonce, an API at `https://api.example.com` reached over HTTPS and WebSockets,
+    /// and images from the app itself, `data:` URIs and a CDN. It is equivalent to:
+    ///
+    /// ```rust
+    /// # use tower_sombrero::headers::{ContentSecurityPolicy, CspSchemeSource, CspSource};
+    /// let host = CspSource::host_static;
+    /// let csp = ContentSecurityPolicy::new_empty()
+    ///     .default_src([CspSource::SelfOrigin])
+    ///     .script_src([CspSource::Nonce, CspSource::StrictDynamic])
+    ///     .style_src([CspSource::SelfOrigin])
+    ///     .connect_src([
+    ///         CspSource::SelfOrigin,
+    ///         host("https://api.example.com"),
+    ///         CspSchemeSource::Wss.into(),
+    ///     ])
+    ///     .img_src([
+    ///         CspSource::SelfOrigin,
+    ///         CspSchemeSource::Data.into(),
+    ///         host("https://cdn.example.com"),
+    ///     ])
+    ///     .object_src([CspSource::None])
+    ///     .base_uri([CspSource::None])
+    ///     .frame_ancestors([CspSource::None]);
+    /// assert_eq!(csp, ContentSecurityPolicy::example_spa());
+    /// assert!(csp.value("n0nce").is_ok());
+    /// ```
+    pub fn example_spa() -> Self {
+        let host = CspSource::host_static;
+        Self {
+            default_src: vec![CspSource::SelfOrigin],
+            script_src: vec![CspSource::Nonce, CspSource::StrictDynamic],
+            style_src: vec![CspSource::SelfOrigin],
+            connect_src: vec![
+                CspSource::SelfOrigin,
+                host("https://api.example.com"),
+                CspSource::Scheme(CspSchemeSource::Wss),
+            ],
+            img_src: vec![
+                CspSource::SelfOrigin,
+                CspSource::Scheme(CspSchemeSource::Data),
+                host("https://cdn.example.com"),
+            ],
+            object_src: vec![CspSource::None],
+            base_uri: vec![CspSource::None],
+            frame_ancestors: vec![CspSource::None],
+            ..Self::new_empty()
+        }
+    }
+
+    /// The minimal policy AMP pages need, following the
+    /// [AMP CSP guide](https://amp.dev/documentation/guides-and-tutorials/optimize-and-measure/secure-pages).
+    ///
+    /// AMP requires `'unsafe-inline'` in both `script-src` and `style-src`: the AMP runtime
+    /// injects inline scripts, and AMP pages keep all their CSS in an inline `<style amp-custom>`.
+    /// That means this policy does not protect against injected inline scripts. It still limits
+    /// where scripts can be loaded from, and blocks plugins and `<base>` hijacking.
+    pub fn for_amp() -> Self {
+        let host = CspSource::host_static;
+        Self {
+            default_src: vec![CspSource::SelfOrigin],
+            script_src: vec![
+                CspSource::UnsafeInline,
+                CspSource::SelfOrigin,
+                host("https://cdn.ampproject.org"),
+                host("*.ampproject.org"),
+                host("*.cdn.ampproject.org"),
+                CspSource::Scheme(CspSchemeSource::Blob),
+            ],
+            style_src: vec![
+                CspSource::UnsafeInline,
+                CspSource::SelfOrigin,
+                host("https://cdn.ampproject.org"),
+            ],
+            img_src: vec![
+                CspSource::SelfOrigin,
+                CspSource::Scheme(CspSchemeSource::Https),
+                CspSource::Scheme(CspSchemeSource::Data),
+            ],
+            font_src: vec![
+                CspSource::SelfOrigin,
+                CspSource::Scheme(CspSchemeSource::Https),
+            ],
+            connect_src: vec![CspSource::SelfOrigin, host("https://cdn.ampproject.org")],
+            object_src: vec![CspSource::None],
+            base_uri: vec![CspSource::SelfOrigin],
+            ..Self::new_empty()
+        }
+    }
 }
 
 impl ContentSecurityPolicy {
+    /// Every source-list directive, with its name, in serialization order.
+    fn directives(&self) -> [(&'static str, &[CspSource]); 23] {
+        [
+            ("default-src", &self.default_src),
+            ("child-src", &self.child_src),
+            ("connect-src", &self.connect_src),
+            ("fetch-src", &self.fetch_src),
+            ("font-src", &self.font_src),
+            ("frame-src", &self.frame_src),
+            ("fenced-frame-src", &self.fenced_frame_src),
+            ("img-src", &self.img_src),
+            ("manifest-src", &self.manifest_src),
+            ("media-src", &self.media_src),
+            ("object-src", &self.object_src),
+            ("script-src", &self.script_src),
+            ("script-src-elem", &self.script_src_elem),
+            ("script-src-attr", &self.script_src_attr),
+            ("style-src", &self.style_src),
+            ("style-src-elem", &self.style_src_elem),
+            ("style-src-attr", &self.style_src_attr),
+            ("worker-src", &self.worker_src),
+            ("base-uri", &self.base_uri),
+            ("sandbox", &self.sandbox),
+            ("form-action", &self.form_action),
+            ("frame-ancestors", &self.frame_ancestors),
+            ("trusted-types", &self.trusted_types),
+        ]
+    }
+
+    /// Compare two policies, ignoring the order and duplication of sources within each directive.
+    /// Useful for testing merged or generated policies, where the derived [`PartialEq`] is too strict.
+    pub fn semantically_eq(&self, other: &Self) -> bool {
+        self.upgrade_insecure_requests == other.upgrade_insecure_requests
+            && self.report_to == other.report_to
+            && self.report_uri == other.report_uri
+            && self
+                .directives()
+                .iter()
+                .zip(other.directives())
+                .all(|((_, a), (_, b))| {
+                    a.iter().collect::<HashSet<_>>() == b.iter().collect::<HashSet<_>>()
+                })
+    }
+
+    /// Like [`Self::value`], but with `extra` appended to `script-src`.
+    /// An unset `script-src` starts out as a copy of `default-src`.
+    pub(crate) fn value_with_script_src(
+        &self,
+        nonce: &str,
+        extra: &[CspSource],
+    ) -> Result<HeaderValue, InvalidHeaderValue> {
+        if extra.is_empty() {
+            return self.value(nonce);
+        }
+        let mut csp = self.clone();
+        if csp.script_src.is_empty() {
+            csp.script_src.clone_from(&csp.default_src);
+        }
+        csp.script_src.extend_from_slice(extra);
+        csp.value(nonce)
+    }
+
+    /// The length of [`Self::value`] with a nonce as long as the ones
+    /// [`Sombrero`](crate::Sombrero) generates, for sizing headers before any request.
+    /// It is exact unless a [`NonceGenerator`](crate::NonceGenerator) makes nonces of
+    /// another length.
+    pub fn estimated_len(&self) -> usize {
+        self.estimated_byte_length(&"0".repeat(crate::NONCE_LENGTH))
+    }
+
+    /// The length of [`Self::value`] for `nonce`, computed without serializing anything.
+    pub fn estimated_byte_length(&self, nonce: &str) -> usize {
+        let directives: usize = self
+            .directives()
+            .into_iter()
+            .filter(|(_, sources)| !sources.is_empty())
+            .map(|(name, sources)| {
+                let sources: usize = sources.iter().map(|s| 1 + s.byte_length(nonce)).sum();
+                name.len() + sources + 1
+            })
+            .sum();
+        let upgrade = if self.upgrade_insecure_requests {
+            UPGRADE_INSECURE_REQUESTS.len()
+        } else {
+            0
+        };
+        let report_uri = self
+            .report_uri
+            .as_ref()
+            .map_or(0, |uri| "report-uri ;".len() + uri.len());
+        let report_to = self
+            .report_to
+            .as_ref()
+            .map_or(0, |group| "report-to ;".len() + group.len());
+        directives + upgrade + report_uri + report_to
+    }
+
+    /// Serialize the policy, with `nonce` in place of [`CspSource::Nonce`].
+    ///
+    /// # Errors
+    ///
+    /// If the policy contains something that can't go in a header, or `report-uri` is not a URI
+    /// or `report-to` is not a group name, as either could end the directive early.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if the policy uses [`CspSource::Nonce`] and `nonce` is empty, which
+    /// would render as the invalid source `'nonce-'`.
     pub fn value(&self, nonce: &str) -> Result<HeaderValue, InvalidHeaderValue> {
-        let mut output = String::with_capacity(256);
-        serialize_header(&mut output, nonce, "default-src", &self.default_src);
-        serialize_header(&mut output, nonce, "child-src", &self.child_src);
-        serialize_header(&mut output, nonce, "connect-src", &self.connect_src);
-        serialize_header(&mut output, nonce, "font-src", &self.font_src);
-        serialize_header(&mut output, nonce, "frame-src", &self.frame_src);
-        serialize_header(&mut output, nonce, "img-src", &self.img_src);
-        serialize_header(&mut output, nonce, "manifest-src", &self.manifest_src);
-        serialize_header(&mut output, nonce, "media-src", &self.media_src);
-        serialize_header(&mut output, nonce, "object-src", &self.object_src);
-        serialize_header(&mut output, nonce, "script-src", &self.script_src);
-        serialize_header(&mut output, nonce, "script-src-elem", &self.script_src_elem);
-        serialize_header(&mut output, nonce, "script-src-attr", &self.script_src_attr);
-        serialize_header(&mut output, nonce, "style-src", &self.style_src);
-        serialize_header(&mut output, nonce, "style-src-elem", &self.style_src_elem);
-        serialize_header(&mut output, nonce, "style-src-attr", &self.style_src_attr);
-        serialize_header(&mut output, nonce, "worker-src", &self.worker_src);
-        serialize_header(&mut output, nonce, "base-uri", &self.base_uri);
-        serialize_header(&mut output, nonce, "sandbox", &self.sandbox);
-        serialize_header(&mut output, nonce, "form-action", &self.form_action);
-        serialize_header(&mut output, nonce, "frame-ancestors", &self.frame_ancestors);
+        debug_assert!(
+            !nonce.is_empty() || !self.uses_nonce(),
+            "a policy using CspSource::Nonce was serialized with an empty nonce"
+        );
+        let report_uri_valid = self.report_uri.as_deref().is_none_or(is_valid_report_uri);
+        let report_to_valid = self.report_to.as_deref().is_none_or(is_valid_report_group);
+        if !report_uri_valid || !report_to_valid {
+            // `InvalidHeaderValue` has no public constructor
+            return Err(HeaderValue::from_bytes(b"\n").unwrap_err());
+        }
+        let directives_empty = self
+            .directives()
+            .iter()
+            .all(|(_, sources)| sources.is_empty());
+        if directives_empty && self.report_to.is_none() && self.report_uri.is_none() {
+            return Ok(if self.upgrade_insecure_requests {
+                HeaderValue::from_static(UPGRADE_INSECURE_REQUESTS)
+            } else {
+                HeaderValue::from_static("")
+            });
+        }
+        let mut output = String::with_capacity(self.estimated_byte_length(nonce));
+        for (name, sources) in self.directives() {
+            serialize_header(
+                &mut output,
+                nonce,
+                self.normalize_source_order,
+                name,
+                sources,
+            );
+        }
+        if self.upgrade_insecure_requests {
+            output.push_str(UPGRADE_INSECURE_REQUESTS);
+        }
+        if let Some(uri) = &self.report_uri {
+            output.push_str("report-uri ");
+            output.push_str(uri);
+            output.push(';');
+        }
+        if let Some(group) = &self.report_to {
+            output.push_str("report-to ");
+            output.push_str(group);
+            output.push(';');
+        }
         HeaderValue::from_str(output.as_str())
     }
 }
@@ -124,6 +408,597 @@ impl ContentSecurityPolicy {
             ..self
         }
     }
+
+    /// Relaxations local development commonly needs: `'unsafe-eval'` in `script-src`, for
+    /// bundlers whose hot module reloading evaluates code, and `ws:`/`wss:` in `connect-src`,
+    /// for their reload sockets. Unset directives start out as a copy of `default-src`.
+    ///
+    /// **Development only.** Keeping every relaxation here makes them easy to audit, and easy to
+    /// leave out of production builds, for example with `if cfg!(debug_assertions)`.
+    #[must_use]
+    pub fn with_dev_relaxations(mut self) -> Self {
+        let additions = [
+            (&mut self.script_src, vec![CspSource::UnsafeEval]),
+            (
+                &mut self.connect_src,
+                vec![
+                    CspSource::Scheme(CspSchemeSource::Ws),
+                    CspSource::Scheme(CspSchemeSource::Wss),
+                ],
+            ),
+        ];
+        for (directive, sources) in additions {
+            if directive.is_empty() {
+                directive.clone_from(&self.default_src);
+            }
+            for source in sources {
+                if !directive.contains(&source) {
+                    directive.push(source);
+                }
+            }
+        }
+        self
+    }
+
+    /// Allow `data:` images, as inlined or base64-encoded images need.
+    /// An unset `img-src` starts out as a copy of `default-src`.
+    #[must_use]
+    pub fn allow_data_images(mut self) -> Self {
+        if self.img_src.is_empty() {
+            self.img_src.clone_from(&self.default_src);
+        }
+        let data = CspSource::Scheme(CspSchemeSource::Data);
+        if !self.img_src.contains(&data) {
+            self.img_src.push(data);
+        }
+        self
+    }
+
+    /// Let the site itself and `origins` embed pages in frames, by setting `frame-ancestors` to
+    /// `'self'` followed by `origins`.
+    ///
+    /// Browsers that support `frame-ancestors` ignore `X-Frame-Options` when it is present, and
+    /// `X-Frame-Options` can't list origins anyway. To set both consistently on a
+    /// [`Sombrero`](crate::Sombrero), use
+    /// [`Sombrero::frame_protection`](crate::Sombrero::frame_protection) instead.
+    #[must_use]
+    pub fn allow_framing_by(self, origins: impl IntoIterator<Item = CspSource>) -> Self {
+        let mut frame_ancestors = vec![CspSource::SelfOrigin];
+        for origin in origins {
+            if !frame_ancestors.contains(&origin) {
+                frame_ancestors.push(origin);
+            }
+        }
+        Self {
+            frame_ancestors,
+            ..self
+        }
+    }
+
+    /// Set `style-src` to `'self'` and the given hashes of your inline styles.
+    /// Unlike [`Self::strict_default`], `'unsafe-inline'` is not included, so any inline
+    /// style that isn't hashed is blocked.
+    #[must_use]
+    pub fn style_hashes(self, hashes: impl IntoIterator<Item = CspSource>) -> Self {
+        let style_src = std::iter::once(CspSource::SelfOrigin)
+            .chain(hashes)
+            .collect();
+        Self { style_src, ..self }
+    }
+
+    /// Send violation reports to the reporting group `group`.
+    #[must_use]
+    pub fn report_to(self, group: impl Into<String>) -> Self {
+        Self {
+            report_to: Some(group.into()),
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn remove_report_to(self) -> Self {
+        Self {
+            report_to: None,
+            ..self
+        }
+    }
+
+    /// Post violation reports to `uri`, for browsers without the Reporting API.
+    #[must_use]
+    pub fn report_uri(self, uri: impl Into<String>) -> Self {
+        Self {
+            report_uri: Some(uri.into()),
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn remove_report_uri(self) -> Self {
+        Self {
+            report_uri: None,
+            ..self
+        }
+    }
+
+    /// Restrict fenced frames exactly as much as regular frames.
+    #[must_use]
+    pub fn copy_frame_src_to_fenced_frame_src(self) -> Self {
+        Self {
+            fenced_frame_src: self.frame_src.clone(),
+            ..self
+        }
+    }
+
+    /// Set `base-uri` to `'self'`, so injected `<base>` tags can't point at other origins.
+    #[must_use]
+    pub fn base_uri_self(self) -> Self {
+        self.base_uri(CspSource::SelfOrigin)
+    }
+
+    /// Set `base-uri` to `'none'`, disallowing `<base>` tags entirely.
+    #[must_use]
+    pub fn base_uri_none(self) -> Self {
+        self.base_uri(CspSource::None)
+    }
+
+    /// Set `object-src` to `'none'`.
+    #[must_use]
+    pub fn object_src_none(self) -> Self {
+        self.object_src(CspSource::None)
+    }
+
+    /// Set `default-src` to `'self'`.
+    #[must_use]
+    pub fn default_src_self(self) -> Self {
+        self.default_src(CspSource::SelfOrigin)
+    }
+
+    /// Set `manifest-src` to `'self'`.
+    ///
+    /// Progressive web apps fail to load their web app manifest when `default-src` is `'none'`
+    /// and `manifest-src` is unset, as the manifest falls back to `default-src`.
+    #[must_use]
+    pub fn allow_manifest(self) -> Self {
+        Self {
+            manifest_src: vec![CspSource::SelfOrigin],
+            ..self
+        }
+    }
+
+    /// Add `'report-sample'` to `script-src`, `script-src-elem`, `style-src`, and `style-src-elem`,
+    /// so violation reports include the first 40 characters of the offending code.
+    ///
+    /// Empty directives are left alone, because a directive containing only `'report-sample'`
+    /// would block everything instead of falling back.
+    ///
+    /// This does nothing unless reports are actually sent somewhere with `report-uri` or `report-to`.
+    #[must_use]
+    pub fn enable_report_sample(mut self) -> Self {
+        for directive in [
+            &mut self.script_src,
+            &mut self.script_src_elem,
+            &mut self.style_src,
+            &mut self.style_src_elem,
+        ] {
+            if !directive.is_empty() && !directive.contains(&CspSource::ReportSample) {
+                directive.push(CspSource::ReportSample);
+            }
+        }
+        self
+    }
+
+    /// See [`Self::normalize_source_order`]. Insertion order is kept by default.
+    #[must_use]
+    pub fn normalize_source_order(self, normalize: bool) -> Self {
+        Self {
+            normalize_source_order: normalize,
+            ..self
+        }
+    }
+}
+
+impl ContentSecurityPolicy {
+    /// Every source-list directive, with its name, mutably. Same order as [`Self::directives`].
+    const fn directives_mut(&mut self) -> [(&'static str, &mut Vec<CspSource>); 23] {
+        [
+            ("default-src", &mut self.default_src),
+            ("child-src", &mut self.child_src),
+            ("connect-src", &mut self.connect_src),
+            ("fetch-src", &mut self.fetch_src),
+            ("font-src", &mut self.font_src),
+            ("frame-src", &mut self.frame_src),
+            ("fenced-frame-src", &mut self.fenced_frame_src),
+            ("img-src", &mut self.img_src),
+            ("manifest-src", &mut self.manifest_src),
+            ("media-src", &mut self.media_src),
+            ("object-src", &mut self.object_src),
+            ("script-src", &mut self.script_src),
+            ("script-src-elem", &mut self.script_src_elem),
+            ("script-src-attr", &mut self.script_src_attr),
+            ("style-src", &mut self.style_src),
+            ("style-src-elem", &mut self.style_src_elem),
+            ("style-src-attr", &mut self.style_src_attr),
+            ("worker-src", &mut self.worker_src),
+            ("base-uri", &mut self.base_uri),
+            ("sandbox", &mut self.sandbox),
+            ("form-action", &mut self.form_action),
+            ("frame-ancestors", &mut self.frame_ancestors),
+            ("trusted-types", &mut self.trusted_types),
+        ]
+    }
+
+    /// Parse a serialized policy, such as the value of a `Content-Security-Policy` header.
+    ///
+    /// Any `'nonce-...'` source becomes [`CspSource::Nonce`], as the nonce is generated per
+    /// request. Like browsers, only the first occurrence of a directive is used.
+    ///
+    /// # Errors
+    /// If the policy contains a directive or a quoted keyword this library does not know.
+    pub fn parse(policy: &str) -> Result<Self, CspParseError> {
+        let mut csp = Self::new_empty();
+        let mut seen = HashSet::new();
+        for directive in policy.split(';') {
+            let mut tokens = directive.split_ascii_whitespace();
+            let Some(name) = tokens.next() else {
+                continue;
+            };
+            let name = name.to_ascii_lowercase();
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            match name.as_str() {
+                "upgrade-insecure-requests" => {
+                    if let Some(value) = tokens.next() {
+                        return Err(CspParseError::UnexpectedValue(value.to_string()));
+                    }
+                    csp.upgrade_insecure_requests = true;
+                }
+                "report-to" => {
+                    let group = tokens
+                        .next()
+                        .ok_or_else(|| CspParseError::MissingValue(name.clone()))?;
+                    csp.report_to = Some(group.to_string());
+                }
+                "report-uri" => {
+                    let uri = tokens
+                        .next()
+                        .ok_or_else(|| CspParseError::MissingValue(name.clone()))?;
+                    csp.report_uri = Some(uri.to_string());
+                }
+                _ => {
+                    let trusted_types = name == "trusted-types";
+                    let sources = tokens
+                        .map(|token| CspSource::parse(token, trusted_types))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let (_, directive) = csp
+                        .directives_mut()
+                        .into_iter()
+                        .find(|(known, _)| *known == name)
+                        .ok_or(CspParseError::UnknownDirective(name))?;
+                    *directive = sources;
+                }
+            }
+        }
+        Ok(csp)
+    }
+}
+
+/// Why [`ContentSecurityPolicy::parse`] failed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, thiserror::Error)]
+pub enum CspParseError {
+    #[error("unknown CSP directive `{0}`")]
+    UnknownDirective(String),
+    #[error("unknown CSP keyword source `{0}`")]
+    UnknownKeyword(String),
+    #[error("CSP directive `{0}` needs a value")]
+    MissingValue(String),
+    #[error("unexpected value `{0}` for a CSP directive that takes none")]
+    UnexpectedValue(String),
+}
+
+/// A source-list directive of a [`ContentSecurityPolicy`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CspDirective {
+    DefaultSrc,
+    ChildSrc,
+    ConnectSrc,
+    FetchSrc,
+    FontSrc,
+    FrameSrc,
+    FencedFrameSrc,
+    ImgSrc,
+    ManifestSrc,
+    MediaSrc,
+    ObjectSrc,
+    ScriptSrc,
+    ScriptSrcElem,
+    ScriptSrcAttr,
+    StyleSrc,
+    StyleSrcElem,
+    StyleSrcAttr,
+    WorkerSrc,
+    BaseUri,
+    Sandbox,
+    FormAction,
+    FrameAncestors,
+    TrustedTypes,
+}
+
+impl CspDirective {
+    /// Every directive, in the order of [`ContentSecurityPolicy::directives`].
+    const ALL: [Self; 23] = [
+        Self::DefaultSrc,
+        Self::ChildSrc,
+        Self::ConnectSrc,
+        Self::FetchSrc,
+        Self::FontSrc,
+        Self::FrameSrc,
+        Self::FencedFrameSrc,
+        Self::ImgSrc,
+        Self::ManifestSrc,
+        Self::MediaSrc,
+        Self::ObjectSrc,
+        Self::ScriptSrc,
+        Self::ScriptSrcElem,
+        Self::ScriptSrcAttr,
+        Self::StyleSrc,
+        Self::StyleSrcElem,
+        Self::StyleSrcAttr,
+        Self::WorkerSrc,
+        Self::BaseUri,
+        Self::Sandbox,
+        Self::FormAction,
+        Self::FrameAncestors,
+        Self::TrustedTypes,
+    ];
+
+    /// The directive name, as it appears in the header.
+    pub fn name(self) -> &'static str {
+        ContentSecurityPolicy::new_empty().directives()[self as usize].0
+    }
+
+    /// The directive browsers use in place of this one when it is not set, per the
+    /// fallback lists of CSP level 3. Document and navigation directives have none.
+    /// `worker-src` tries `script-src` between `child-src` and `default-src`, which
+    /// [`ContentSecurityPolicy::effective_sources_for`] accounts for.
+    pub const fn fallback(self) -> Option<Self> {
+        match self {
+            Self::ScriptSrcElem | Self::ScriptSrcAttr => Some(Self::ScriptSrc),
+            Self::StyleSrcElem | Self::StyleSrcAttr => Some(Self::StyleSrc),
+            Self::WorkerSrc => Some(Self::ChildSrc),
+            Self::FencedFrameSrc => Some(Self::FrameSrc),
+            Self::FrameSrc => Some(Self::ChildSrc),
+            Self::ChildSrc
+            | Self::ConnectSrc
+            | Self::FetchSrc
+            | Self::FontSrc
+            | Self::ImgSrc
+            | Self::ManifestSrc
+            | Self::MediaSrc
+            | Self::ObjectSrc
+            | Self::ScriptSrc
+            | Self::StyleSrc => Some(Self::DefaultSrc),
+            Self::DefaultSrc
+            | Self::BaseUri
+            | Self::Sandbox
+            | Self::FormAction
+            | Self::FrameAncestors
+            | Self::TrustedTypes => None,
+        }
+    }
+}
+
+impl ContentSecurityPolicy {
+    /// The sources of `directive`. Empty if it is not set.
+    pub fn sources(&self, directive: CspDirective) -> &[CspSource] {
+        // `CspDirective` variants are declared in the order of `directives`.
+        self.directives()[directive as usize].1
+    }
+
+    /// Whether `directive` is set, meaning it has at least one source.
+    pub fn has_directive(&self, directive: CspDirective) -> bool {
+        !self.sources(directive).is_empty()
+    }
+
+    /// The number of sources in `directive`.
+    pub fn directive_source_count(&self, directive: CspDirective) -> usize {
+        self.sources(directive).len()
+    }
+
+    /// The sources browsers enforce for `directive`: its own if it is set, otherwise those of
+    /// the first set directive in its fallback chain, ending at `default-src`.
+    /// `worker-src` falls back to `child-src`, then `script-src`.
+    pub fn effective_sources_for(&self, directive: CspDirective) -> &[CspSource] {
+        let mut current = directive;
+        loop {
+            let sources = self.sources(current);
+            if !sources.is_empty() {
+                return sources;
+            }
+            current = match (directive, current) {
+                (CspDirective::WorkerSrc, CspDirective::ChildSrc) => CspDirective::ScriptSrc,
+                _ => match current.fallback() {
+                    Some(next) => next,
+                    None => return sources,
+                },
+            };
+        }
+    }
+
+    /// A copy of this policy with every unset directive filled in from its fallback,
+    /// so that each directive lists exactly what browsers enforce for it.
+    #[must_use]
+    pub fn effective_policy(&self) -> Self {
+        let mut effective = self.clone();
+        let sources = CspDirective::ALL.map(|directive| self.effective_sources_for(directive));
+        for ((_, target), sources) in effective.directives_mut().into_iter().zip(sources) {
+            if target.is_empty() {
+                target.extend_from_slice(sources);
+            }
+        }
+        effective
+    }
+
+    /// Whether this policy allows everything `other` allows, and something more.
+    ///
+    /// Directives are compared by their [effective sources](Self::effective_sources_for):
+    /// an unset directive allows anything, `'none'` allows nothing, and otherwise a directive
+    /// allows more if its sources are a superset. Turning off `upgrade-insecure-requests` also
+    /// counts as allowing more. Overlapping sources, like a host and a scheme covering it,
+    /// are not resolved, so some policies are incomparable even though one is stricter.
+    pub fn is_strictly_more_permissive_than(&self, other: &Self) -> bool {
+        self.is_at_least_as_permissive_as(other) && !other.is_at_least_as_permissive_as(self)
+    }
+
+    pub(crate) fn is_at_least_as_permissive_as(&self, other: &Self) -> bool {
+        let allows_at_least = |ours: &[CspSource], theirs: &[CspSource]| {
+            if ours.is_empty() {
+                return true;
+            }
+            if theirs.is_empty() {
+                return false;
+            }
+            let allowing = |source: &&CspSource| {
+                !matches!(source, CspSource::None | CspSource::TrustedTypeNone)
+            };
+            let ours: HashSet<_> = ours.iter().filter(allowing).collect();
+            theirs
+                .iter()
+                .filter(allowing)
+                .all(|source| ours.contains(source))
+        };
+        let directives = CspDirective::ALL.into_iter().all(|directive| {
+            allows_at_least(
+                self.effective_sources_for(directive),
+                other.effective_sources_for(directive),
+            )
+        });
+        directives && (!self.upgrade_insecure_requests || other.upgrade_insecure_requests)
+    }
+
+    /// Whether any directive contains `source`.
+    pub fn any_directive_contains(&self, source: &CspSource) -> bool {
+        self.directives()
+            .into_iter()
+            .any(|(_, sources)| sources.contains(source))
+    }
+}
+
+/// How much protection against cross-site scripting a [`ContentSecurityPolicy`] gives,
+/// ordered from least to most. See [`ContentSecurityPolicy::minimum_security_level`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CspSecurityLevel {
+    /// Scripts are not restricted at all.
+    None,
+    /// Script sources are restricted, but inline scripts or `eval` are allowed.
+    Basic,
+    /// Neither `'unsafe-inline'` nor `'unsafe-eval'` are allowed.
+    Moderate,
+    /// Moderate, and scripts are allowed by nonce or hash.
+    Strict,
+    /// Strict, using a nonce with `'strict-dynamic'` and without `'unsafe-hashes'`.
+    Paranoid,
+}
+
+impl ContentSecurityPolicy {
+    /// Classify the protection this policy gives against cross-site scripting, going by the
+    /// effective `script-src`. `'unsafe-inline'` next to a nonce or hash doesn't count,
+    /// because browsers ignore it then.
+    pub fn minimum_security_level(&self) -> CspSecurityLevel {
+        let script_src = self.effective_sources_for(CspDirective::ScriptSrc);
+        if script_src.is_empty() {
+            return CspSecurityLevel::None;
+        }
+        let has = |source: &CspSource| script_src.contains(source);
+        let nonce = has(&CspSource::Nonce);
+        let nonce_or_hash = nonce || script_src.iter().any(|s| matches!(s, CspSource::Hash(..)));
+        if (has(&CspSource::UnsafeInline) && !nonce_or_hash) || has(&CspSource::UnsafeEval) {
+            CspSecurityLevel::Basic
+        } else if !nonce_or_hash {
+            CspSecurityLevel::Moderate
+        } else if nonce && has(&CspSource::StrictDynamic) && !has(&CspSource::UnsafeHashes) {
+            CspSecurityLevel::Paranoid
+        } else {
+            CspSecurityLevel::Strict
+        }
+    }
+}
+
+/// Something in a [`ContentSecurityPolicy`] that is probably not what was intended.
+/// These are advisory: the policy will still serialize.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CspWarning {
+    /// `frame-src` is set but `fenced-frame-src` is not, so fenced frames silently
+    /// inherit the `frame-src` list. See
+    /// [`ContentSecurityPolicy::copy_frame_src_to_fenced_frame_src`] to make this explicit.
+    FencedFrameSrcInheritsFrameSrc,
+    /// `script-src` contains `'strict-dynamic'`, which makes browsers ignore its host and scheme
+    /// sources. Only `https:` and `'unsafe-inline'`, as fallbacks for old browsers, make sense
+    /// next to it.
+    StrictDynamicIgnoresHostSources,
+    /// `trusted-types` contains `'none'` next to other sources. `'none'` must be alone.
+    TrustedTypesNoneNotAlone,
+    /// `source` is not allowed in `directive` and browsers will ignore it, such as a nonce
+    /// in `frame-ancestors`, which is not a fetch directive.
+    InvalidSourceInDirective {
+        directive: CspDirective,
+        source: CspSource,
+    },
+}
+
+impl ContentSecurityPolicy {
+    /// Look for common CSP authoring mistakes.
+    pub fn validate(&self) -> Vec<CspWarning> {
+        let mut warnings = Vec::new();
+        if self.fenced_frame_src.is_empty() && !self.frame_src.is_empty() {
+            warnings.push(CspWarning::FencedFrameSrcInheritsFrameSrc);
+        }
+        if self.script_src.contains(&CspSource::StrictDynamic)
+            && self.script_src.iter().any(|source| match source {
+                CspSource::Host(_) | CspSource::Wildcard => true,
+                CspSource::Scheme(scheme) => *scheme != CspSchemeSource::Https,
+                _ => false,
+            })
+        {
+            warnings.push(CspWarning::StrictDynamicIgnoresHostSources);
+        }
+        if self.trusted_types.len() > 1
+            && self
+                .trusted_types
+                .iter()
+                .any(|source| matches!(source, CspSource::None | CspSource::TrustedTypeNone))
+        {
+            warnings.push(CspWarning::TrustedTypesNoneNotAlone);
+        }
+        if self.frame_ancestors.contains(&CspSource::Nonce) {
+            warnings.push(CspWarning::InvalidSourceInDirective {
+                directive: CspDirective::FrameAncestors,
+                source: CspSource::Nonce,
+            });
+        }
+        warnings
+    }
+
+    /// Whether any directive contains [`CspSource::Nonce`].
+    pub fn uses_nonce(&self) -> bool {
+        self.directives()
+            .into_iter()
+            .any(|(_, sources)| sources.contains(&CspSource::Nonce))
+    }
+
+    /// Whether this policy only works when the page is served over HTTPS.
+    ///
+    /// This is the case when `upgrade-insecure-requests` is enabled, or when some directive
+    /// allows `https:` while none allow `http:`.
+    pub fn requires_https(&self) -> bool {
+        if self.upgrade_insecure_requests {
+            return true;
+        }
+        let sources = || self.directives().into_iter().flat_map(|(_, s)| s.iter());
+        let https_only = sources().any(|s| *s == CspSource::Scheme(CspSchemeSource::Https));
+        let allows_http = sources().any(|s| *s == CspSource::Scheme(CspSchemeSource::Http));
+        https_only && !allows_http
+    }
 }
 
 macro_rules! csp_builder_add {
@@ -151,13 +1026,29 @@ macro_rules! csp_builder_remove {
     };
 }
 
+macro_rules! csp_builder_deny {
+    ($id:ident, $func:ident) => {
+        /// Set this directive to `'none'`, blocking it whatever `default-src` allows.
+        /// The `remove_` method omits it instead, so it falls back.
+        #[must_use]
+        pub fn $func(self) -> Self {
+            Self {
+                $id: ::std::vec![$crate::headers::csp::CspSource::None],
+                ..self
+            }
+        }
+    };
+}
+
 #[rustfmt::skip]
 impl ContentSecurityPolicy { 
     csp_builder_add!(default_src);
     csp_builder_add!(child_src);
     csp_builder_add!(connect_src);
+    csp_builder_add!(fetch_src);
     csp_builder_add!(font_src);
     csp_builder_add!(frame_src);
+    csp_builder_add!(fenced_frame_src);
     csp_builder_add!(img_src);
     csp_builder_add!(manifest_src);
     csp_builder_add!(media_src);
@@ -173,11 +1064,14 @@ impl ContentSecurityPolicy {
     csp_builder_add!(sandbox);
     csp_builder_add!(form_action);
     csp_builder_add!(frame_ancestors);
+    csp_builder_add!(trusted_types);
     csp_builder_remove!(default_src, remove_default_src);
     csp_builder_remove!(child_src, remove_child_src);
     csp_builder_remove!(connect_src, remove_connect_src);
+    csp_builder_remove!(fetch_src, remove_fetch_src);
     csp_builder_remove!(font_src, remove_font_src);
     csp_builder_remove!(frame_src, remove_frame_src);
+    csp_builder_remove!(fenced_frame_src, remove_fenced_frame_src);
     csp_builder_remove!(img_src, remove_img_src);
     csp_builder_remove!(manifest_src, remove_manifest_src);
     csp_builder_remove!(media_src, remove_media_src);
@@ -193,6 +1087,28 @@ impl ContentSecurityPolicy {
     csp_builder_remove!(sandbox, remove_sandbox);
     csp_builder_remove!(form_action, remove_form_action);
     csp_builder_remove!(frame_ancestors, remove_frame_ancestors);
+    csp_builder_remove!(trusted_types, remove_trusted_types);
+    csp_builder_deny!(default_src, deny_default_src);
+    csp_builder_deny!(child_src, deny_child_src);
+    csp_builder_deny!(connect_src, deny_connect_src);
+    csp_builder_deny!(fetch_src, deny_fetch_src);
+    csp_builder_deny!(font_src, deny_font_src);
+    csp_builder_deny!(frame_src, deny_frame_src);
+    csp_builder_deny!(fenced_frame_src, deny_fenced_frame_src);
+    csp_builder_deny!(img_src, deny_img_src);
+    csp_builder_deny!(manifest_src, deny_manifest_src);
+    csp_builder_deny!(media_src, deny_media_src);
+    csp_builder_deny!(object_src, deny_object_src);
+    csp_builder_deny!(script_src, deny_script_src);
+    csp_builder_deny!(script_src_elem, deny_script_src_elem);
+    csp_builder_deny!(script_src_attr, deny_script_src_attr);
+    csp_builder_deny!(style_src, deny_style_src);
+    csp_builder_deny!(style_src_elem, deny_style_src_elem);
+    csp_builder_deny!(style_src_attr, deny_style_src_attr);
+    csp_builder_deny!(worker_src, deny_worker_src);
+    csp_builder_deny!(base_uri, deny_base_uri);
+    csp_builder_deny!(form_action, deny_form_action);
+    csp_builder_deny!(frame_ancestors, deny_frame_ancestors);
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -203,6 +1119,8 @@ pub enum CspSchemeSource {
     Filesystem,
     Http,
     Https,
+    Ws,
+    Wss,
 }
 
 impl AsRef<str> for CspSchemeSource {
@@ -214,6 +1132,8 @@ impl AsRef<str> for CspSchemeSource {
             Self::Filesystem => "filesystem:",
             Self::Http => "http:",
             Self::Https => "https:",
+            Self::Ws => "ws:",
+            Self::Wss => "wss:",
         }
     }
 }
@@ -245,7 +1165,13 @@ impl AsRef<str> for CspHashAlgorithm {
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum CspSource {
-    Host(String),
+    /// A host or origin, like `https://cdn.example.com` or `*.example.com`. Use
+    /// [`Self::host_static`] for hosts known at compile time, which avoids allocating, and
+    /// [`Self::host`] to validate one. A `String` converts with `.into()`.
+    Host(Cow<'static, str>),
+    /// `*`, which allows any URL except `data:`, `blob:` and `filesystem:` ones.
+    /// Prefer anything more specific.
+    Wildcard,
     Scheme(CspSchemeSource),
     /// Nonce has special handling by the library.
     Nonce,
@@ -260,12 +1186,21 @@ pub enum CspSource {
     ReportSample,
     InlineSpeculationRules,
     None,
+    /// A Trusted Types policy name, for `trusted-types`. Unlike keywords, names are not quoted.
+    TrustedTypePolicy(String),
+    /// `*` in `trusted-types`, allowing policies with any name.
+    TrustedTypeStar,
+    /// `'none'` in `trusted-types`, forbidding every policy. It must be the only source.
+    TrustedTypeNone,
+    /// `'allow-duplicates'` in `trusted-types`, allowing several policies with the same name.
+    AllowDuplicates,
 }
 
 impl CspSource {
     fn as_cow(&self, nonce: &str) -> Cow<'_, str> {
         let borrowed = match self {
-            Self::Host(s) => s.as_str(),
+            Self::Host(s) => s,
+            Self::Wildcard => "*",
             Self::Scheme(s) => s.as_ref(),
             Self::Nonce => return Cow::Owned(format!("'nonce-{nonce}'")),
             Self::Hash(algo, data) => return Cow::Owned(format!("'{}-{data}'", algo.as_ref())),
@@ -277,10 +1212,184 @@ impl CspSource {
             Self::StrictDynamic => "'strict-dynamic'",
             Self::ReportSample => "'report-sample'",
             Self::InlineSpeculationRules => "'inline-speculation-rules'",
-            Self::None => "'none'",
+            Self::None | Self::TrustedTypeNone => "'none'",
+            Self::TrustedTypePolicy(name) => name.as_str(),
+            Self::TrustedTypeStar => "*",
+            Self::AllowDuplicates => "'allow-duplicates'",
         };
         Cow::Borrowed(borrowed)
     }
+
+    /// The length of [`Self::as_cow`], without allocating.
+    fn byte_length(&self, nonce: &str) -> usize {
+        match self {
+            Self::Nonce => "'nonce-'".len() + nonce.len(),
+            Self::Hash(algo, data) => "'-'".len() + algo.as_ref().len() + data.len(),
+            other => other.as_cow(nonce).len(),
+        }
+    }
+
+    /// Sort key for [`ContentSecurityPolicy::normalize_source_order`]
+    const fn order_rank(&self) -> u8 {
+        match self {
+            Self::Scheme(_) => 1,
+            Self::Host(_) | Self::Wildcard => 2,
+            Self::Nonce | Self::Hash(_, _) => 3,
+            _ => 0,
+        }
+    }
+}
+
+impl CspSource {
+    /// Parse one serialized source. `trusted_types` selects the meaning of `*`, `'none'` and
+    /// unquoted names in the `trusted-types` directive.
+    fn parse(token: &str, trusted_types: bool) -> Result<Self, CspParseError> {
+        if trusted_types {
+            match token {
+                "*" => return Ok(Self::TrustedTypeStar),
+                "'none'" => return Ok(Self::TrustedTypeNone),
+                "'allow-duplicates'" => return Ok(Self::AllowDuplicates),
+                _ if !token.starts_with('\'') => {
+                    return Ok(Self::TrustedTypePolicy(token.to_string()))
+                }
+                _ => {}
+            }
+        }
+        let source = match token.to_ascii_lowercase().as_str() {
+            "*" => Self::Wildcard,
+            "data:" => Self::Scheme(CspSchemeSource::Data),
+            "mediastream:" => Self::Scheme(CspSchemeSource::Mediastream),
+            "blob:" => Self::Scheme(CspSchemeSource::Blob),
+            "filesystem:" => Self::Scheme(CspSchemeSource::Filesystem),
+            "http:" => Self::Scheme(CspSchemeSource::Http),
+            "https:" => Self::Scheme(CspSchemeSource::Https),
+            "ws:" => Self::Scheme(CspSchemeSource::Ws),
+            "wss:" => Self::Scheme(CspSchemeSource::Wss),
+            "'self'" => Self::SelfOrigin,
+            "'unsafe-eval'" => Self::UnsafeEval,
+            "'wasm-unsafe-eval'" => Self::WasmUnsafeEval,
+            "'unsafe-hashes'" => Self::UnsafeHashes,
+            "'unsafe-inline'" => Self::UnsafeInline,
+            "'strict-dynamic'" => Self::StrictDynamic,
+            "'report-sample'" => Self::ReportSample,
+            "'inline-speculation-rules'" => Self::InlineSpeculationRules,
+            "'none'" => Self::None,
+            lower if lower.starts_with("'nonce-") => Self::Nonce,
+            _ => {
+                let Some(quoted) = token.strip_prefix('\'') else {
+                    return Ok(Self::Host(token.to_string().into()));
+                };
+                let inner = quoted.strip_suffix('\'').unwrap_or(quoted);
+                let algorithm = match inner.split_once('-') {
+                    Some(("sha256", digest)) => Some((CspHashAlgorithm::Sha256, digest)),
+                    Some(("sha384", digest)) => Some((CspHashAlgorithm::Sha384, digest)),
+                    Some(("sha512", digest)) => Some((CspHashAlgorithm::Sha512, digest)),
+                    _ => None,
+                };
+                let (algorithm, digest) =
+                    algorithm.ok_or_else(|| CspParseError::UnknownKeyword(token.to_string()))?;
+                Self::Hash(algorithm, digest.to_string())
+            }
+        };
+        Ok(source)
+    }
+}
+
+/// Why [`CspSource::host`] or one of the hash constructors refused to build a source.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, thiserror::Error)]
+pub enum InvalidCspSource {
+    #[error("CSP host sources cannot be empty")]
+    Empty,
+    #[error("CSP host source contains invalid character {0:?}")]
+    InvalidCharacter(char),
+    #[error(
+        "a bare `*` host allows nearly everything; use `CspSource::Wildcard` if that is intended"
+    )]
+    BareWildcard,
+    #[error(
+        "CSP host sources can't be bare paths; use `CspSource::SelfOrigin` for same-origin resources"
+    )]
+    RelativePath,
+    #[error("a {algorithm} digest is {expected} base64 characters long, not {found}")]
+    HashLength {
+        algorithm: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    #[error("CSP hash digest contains non-base64 character {0:?}")]
+    InvalidBase64(char),
+}
+
+impl CspSource {
+    /// Create a [`CspSource::Host`], checking that it can't break out of its directive.
+    /// A bare `*` is rejected, as it is almost never intended. Use [`CspSource::Wildcard`] for that.
+    /// Paths like `/static` are also rejected, as they are not valid sources. `'self'` is probably
+    /// what was meant.
+    pub fn host(host: impl Into<String>) -> Result<Self, InvalidCspSource> {
+        let host = host.into();
+        if host.is_empty() {
+            return Err(InvalidCspSource::Empty);
+        }
+        if host == "*" {
+            return Err(InvalidCspSource::BareWildcard);
+        }
+        if host.starts_with('/') {
+            return Err(InvalidCspSource::RelativePath);
+        }
+        if let Some(c) = host
+            .chars()
+            .find(|c| c.is_whitespace() || c.is_control() || matches!(c, ';' | ',' | '\''))
+        {
+            return Err(InvalidCspSource::InvalidCharacter(c));
+        }
+        Ok(Self::Host(Cow::Owned(host)))
+    }
+
+    /// A [`CspSource::Host`] borrowing `host`, without allocating. Unlike [`Self::host`], this
+    /// doesn't validate `host`.
+    pub const fn host_static(host: &'static str) -> Self {
+        Self::Host(Cow::Borrowed(host))
+    }
+
+    /// Create a [`CspSource::Hash`] from a base64 (or base64url) encoded SHA-256 digest.
+    pub fn sha256(digest: impl Into<String>) -> Result<Self, InvalidCspSource> {
+        Self::hash(CspHashAlgorithm::Sha256, "sha256", 32, digest.into())
+    }
+
+    /// Create a [`CspSource::Hash`] from a base64 (or base64url) encoded SHA-384 digest.
+    pub fn sha384(digest: impl Into<String>) -> Result<Self, InvalidCspSource> {
+        Self::hash(CspHashAlgorithm::Sha384, "sha384", 48, digest.into())
+    }
+
+    /// Create a [`CspSource::Hash`] from a base64 (or base64url) encoded SHA-512 digest.
+    pub fn sha512(digest: impl Into<String>) -> Result<Self, InvalidCspSource> {
+        Self::hash(CspHashAlgorithm::Sha512, "sha512", 64, digest.into())
+    }
+
+    fn hash(
+        algorithm: CspHashAlgorithm,
+        name: &'static str,
+        digest_bytes: usize,
+        digest: String,
+    ) -> Result<Self, InvalidCspSource> {
+        let unpadded = digest.trim_end_matches('=');
+        if let Some(c) = unpadded
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '-' | '_')))
+        {
+            return Err(InvalidCspSource::InvalidBase64(c));
+        }
+        let expected = digest_bytes.div_ceil(3) * 4;
+        let expected_unpadded = (digest_bytes * 4).div_ceil(3);
+        if unpadded.len() != expected_unpadded || digest.len() > expected {
+            return Err(InvalidCspSource::HashLength {
+                algorithm: name,
+                expected,
+                found: digest.len(),
+            });
+        }
+        Ok(Self::Hash(algorithm, digest))
+    }
 }
 
 impl From<CspSource> for Vec<CspSource> {
@@ -289,10 +1398,62 @@ impl From<CspSource> for Vec<CspSource> {
     }
 }
 
-fn serialize_header(s: &mut String, nonce: &str, name: &str, sources: &[CspSource]) {
+/// Whether `uri` is an absolute URI like `https://example.com/csp`, or a path like `/csp`,
+/// made only of the printable ASCII characters URIs allow, and without `;` or `,`.
+fn is_valid_report_uri(uri: &str) -> bool {
+    if uri.is_empty()
+        || !uri
+            .bytes()
+            .all(|b| b.is_ascii_graphic() && !matches!(b, b';' | b',' | b'"' | b'<' | b'>'))
+    {
+        return false;
+    }
+    if uri.starts_with('/') {
+        return true;
+    }
+    let Some((scheme, rest)) = uri.split_once(':') else {
+        return false;
+    };
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        && !rest.is_empty()
+}
+
+/// Whether `group` is a valid reporting group name, like `csp-endpoint`.
+fn is_valid_report_group(group: &str) -> bool {
+    !group.is_empty()
+        && group
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+fn serialize_header(
+    s: &mut String,
+    nonce: &str,
+    normalize: bool,
+    name: &str,
+    sources: &[CspSource],
+) {
     if sources.is_empty() {
         return;
     }
+    if normalize {
+        let mut sorted: Vec<&CspSource> = sources.iter().collect();
+        sorted.sort_by_key(|source| source.order_rank());
+        serialize_sources(s, nonce, name, sorted);
+    } else {
+        serialize_sources(s, nonce, name, sources);
+    }
+}
+
+fn serialize_sources<'a>(
+    s: &mut String,
+    nonce: &str,
+    name: &str,
+    sources: impl IntoIterator<Item = &'a CspSource>,
+) {
     s.push_str(name);
     for source in sources {
         s.push(' ');
@@ -300,3 +1461,635 @@ fn serialize_header(s: &mut String, nonce: &str, name: &str, sources: &[CspSourc
     }
     s.push(';');
 }
+
+#[cfg(test)]
+#[test]
+fn normalized_source_order() {
+    let csp = ContentSecurityPolicy::new_empty()
+        .script_src([
+            CspSource::Nonce,
+            CspSource::host_static("https://cdn.example.com"),
+            CspSource::Scheme(CspSchemeSource::Https),
+            CspSource::SelfOrigin,
+            CspSource::UnsafeInline,
+        ])
+        .normalize_source_order(true);
+    assert_eq!(
+        csp.value("abc").unwrap(),
+        "script-src 'self' 'unsafe-inline' https: https://cdn.example.com 'nonce-abc';"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn copy_frame_src_to_fenced_frame_src() {
+    let csp = ContentSecurityPolicy::new_empty().frame_src(CspSource::SelfOrigin);
+    assert_eq!(
+        csp.validate(),
+        vec![CspWarning::FencedFrameSrcInheritsFrameSrc]
+    );
+    let csp = csp.copy_frame_src_to_fenced_frame_src();
+    assert_eq!(csp.fenced_frame_src, vec![CspSource::SelfOrigin]);
+    assert_eq!(csp.validate(), vec![]);
+    assert_eq!(
+        csp.value("").unwrap(),
+        "frame-src 'self';fenced-frame-src 'self';"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn enable_report_sample() {
+    let csp = ContentSecurityPolicy::new_empty()
+        .script_src(CspSource::SelfOrigin)
+        .script_src_elem(CspSource::SelfOrigin)
+        .style_src(CspSource::SelfOrigin)
+        .style_src_elem(CspSource::SelfOrigin)
+        .enable_report_sample()
+        .enable_report_sample();
+    assert_eq!(
+        csp.value("").unwrap(),
+        "script-src 'self' 'report-sample';script-src-elem 'self' 'report-sample';\
+         style-src 'self' 'report-sample';style-src-elem 'self' 'report-sample';"
+    );
+    let csp = ContentSecurityPolicy::new_empty().enable_report_sample();
+    assert_eq!(csp.value("").unwrap(), "");
+}
+
+#[cfg(test)]
+#[test]
+fn wildcard_source() {
+    let csp = ContentSecurityPolicy::new_empty().img_src(CspSource::Wildcard);
+    assert_eq!(csp.value("").unwrap(), "img-src *;");
+    assert_eq!(CspSource::host("*"), Err(InvalidCspSource::BareWildcard));
+    assert_eq!(
+        CspSource::host("*.example.com"),
+        Ok(CspSource::host_static("*.example.com"))
+    );
+    assert_eq!(
+        CspSource::host("example.com; script-src *"),
+        Err(InvalidCspSource::InvalidCharacter(';'))
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn allow_manifest() {
+    let csp = ContentSecurityPolicy::new_empty()
+        .default_src(CspSource::None)
+        .allow_manifest();
+    assert_eq!(
+        csp.value("").unwrap(),
+        "default-src 'none';manifest-src 'self';"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn single_source_conveniences() {
+    let csp = ContentSecurityPolicy::new_empty();
+    assert_eq!(
+        csp.clone().base_uri_self().base_uri,
+        [CspSource::SelfOrigin]
+    );
+    assert_eq!(csp.clone().base_uri_none().base_uri, [CspSource::None]);
+    assert_eq!(csp.clone().object_src_none().object_src, [CspSource::None]);
+    assert_eq!(csp.default_src_self().default_src, [CspSource::SelfOrigin]);
+}
+
+#[cfg(test)]
+#[test]
+fn semantically_eq_ignores_order() {
+    let a = ContentSecurityPolicy::new_empty()
+        .script_src([CspSource::SelfOrigin, CspSource::Nonce])
+        .img_src([CspSource::Wildcard]);
+    let b = ContentSecurityPolicy::new_empty()
+        .script_src([CspSource::Nonce, CspSource::SelfOrigin])
+        .img_src([CspSource::Wildcard]);
+    assert_ne!(a, b);
+    assert!(a.semantically_eq(&b));
+    assert!(!a.semantically_eq(&b.clone().remove_img_src()));
+    assert!(!a.semantically_eq(&b.upgrade_insecure_requests(true)));
+}
+
+#[cfg(test)]
+#[test]
+fn host_rejects_relative_path() {
+    let err = CspSource::host("/static").unwrap_err();
+    assert_eq!(err, InvalidCspSource::RelativePath);
+    assert!(err.to_string().contains("`CspSource::SelfOrigin`"));
+    assert!(CspSource::host("https://example.com/static/").is_ok());
+}
+
+#[cfg(test)]
+#[test]
+fn requires_https_conditions() {
+    let empty = ContentSecurityPolicy::new_empty();
+    assert!(!empty.requires_https());
+    assert!(empty
+        .clone()
+        .upgrade_insecure_requests(true)
+        .requires_https());
+
+    let https_only = empty.img_src([CspSchemeSource::Https.into()]);
+    assert!(https_only.requires_https());
+
+    let mixed = https_only.connect_src([CspSchemeSource::Http.into()]);
+    assert!(!mixed.requires_https());
+    assert!(mixed.upgrade_insecure_requests(true).requires_https());
+}
+
+#[cfg(test)]
+#[test]
+fn hash_constructors_check_length() {
+    let sha256 = "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=";
+    assert_eq!(
+        CspSource::sha256(sha256),
+        Ok(CspSource::Hash(
+            CspHashAlgorithm::Sha256,
+            sha256.to_string()
+        ))
+    );
+    assert!(CspSource::sha256(sha256.trim_end_matches('=')).is_ok());
+    let sha384 = "OLBgp1GsljhM2TJ+sbHjaiH9txEUvgdDTAzHv2P24donTt6/529l+9Ua0vFImLlb";
+    assert!(CspSource::sha384(sha384).is_ok());
+    let sha512 =
+        "z4PhNX7vuL3xVChQ1m2AB9Yg5AULVxXcg/SpIdNs6c5H0NE8XYXysP+DGNKHfuwvY7kxvUdBeoGlODJ6+SfaPg==";
+    assert!(CspSource::sha512(sha512).is_ok());
+
+    assert_eq!(
+        CspSource::sha384(sha256),
+        Err(InvalidCspSource::HashLength {
+            algorithm: "sha384",
+            expected: 64,
+            found: 44
+        })
+    );
+    assert!(CspSource::sha512(sha384).is_err());
+    assert!(CspSource::sha256(sha512).is_err());
+    assert_eq!(
+        CspSource::sha256("47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuF'="),
+        Err(InvalidCspSource::InvalidBase64('\''))
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn strict_dynamic_with_hosts_warns() {
+    let csp = ContentSecurityPolicy::new_empty().script_src([
+        CspSource::Nonce,
+        CspSource::StrictDynamic,
+        CspSchemeSource::Https.into(),
+        CspSource::UnsafeInline,
+    ]);
+    assert!(csp.validate().is_empty());
+    let mut with_host = csp;
+    with_host
+        .script_src
+        .push(CspSource::host_static("cdn.example.com"));
+    assert_eq!(
+        with_host.validate(),
+        [CspWarning::StrictDynamicIgnoresHostSources]
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn trusted_types_serialization() {
+    let csp = ContentSecurityPolicy::new_empty().trusted_types([
+        CspSource::TrustedTypePolicy("default".to_string()),
+        CspSource::TrustedTypePolicy("dompurify".to_string()),
+        CspSource::AllowDuplicates,
+    ]);
+    assert_eq!(
+        csp.value("").unwrap(),
+        "trusted-types default dompurify 'allow-duplicates';"
+    );
+    assert!(csp.validate().is_empty());
+
+    let star = ContentSecurityPolicy::new_empty().trusted_types([CspSource::TrustedTypeStar]);
+    assert_eq!(star.value("").unwrap(), "trusted-types *;");
+
+    let none = ContentSecurityPolicy::new_empty().trusted_types([CspSource::TrustedTypeNone]);
+    assert_eq!(none.value("").unwrap(), "trusted-types 'none';");
+    assert!(none.validate().is_empty());
+    let mixed = none.trusted_types([
+        CspSource::TrustedTypeNone,
+        CspSource::TrustedTypePolicy("default".to_string()),
+    ]);
+    assert_eq!(mixed.validate(), [CspWarning::TrustedTypesNoneNotAlone]);
+}
+
+#[cfg(test)]
+#[test]
+fn style_hashes_drop_unsafe_inline() {
+    let hash = CspSource::Hash(CspHashAlgorithm::Sha256, "abc=".to_string());
+    let csp = ContentSecurityPolicy::strict_default().style_hashes([hash.clone()]);
+    assert!(!csp.style_src.contains(&CspSource::UnsafeInline));
+    assert_eq!(csp.style_src, [CspSource::SelfOrigin, hash]);
+    let value = csp.value("").unwrap();
+    assert!(value
+        .to_str()
+        .unwrap()
+        .contains("style-src 'self' 'sha256-abc=';"));
+}
+
+#[cfg(test)]
+#[test]
+fn deny_sets_none_instead_of_omitting() {
+    let csp = ContentSecurityPolicy::new_empty()
+        .default_src([CspSource::SelfOrigin])
+        .deny_object_src();
+    assert_eq!(csp.object_src, [CspSource::None]);
+    assert_eq!(
+        csp.value("").unwrap(),
+        "default-src 'self';object-src 'none';"
+    );
+    assert_eq!(
+        csp.remove_object_src().value("").unwrap(),
+        "default-src 'self';"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn static_host_matches_owned_host() {
+    const CDN: CspSource = CspSource::host_static("https://cdn.example.com");
+    let owned = CspSource::Host("https://cdn.example.com".to_string().into());
+    assert!(matches!(&CDN, CspSource::Host(Cow::Borrowed(_))));
+    assert_eq!(CDN, owned);
+    assert_eq!(
+        ContentSecurityPolicy::new_empty()
+            .img_src([CDN])
+            .value("")
+            .unwrap(),
+        ContentSecurityPolicy::new_empty()
+            .img_src([owned])
+            .value("")
+            .unwrap()
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn allow_framing_by_lists_origins() {
+    let csp = ContentSecurityPolicy::new_empty().allow_framing_by([
+        CspSource::host_static("https://partner.example.com"),
+        CspSource::SelfOrigin,
+        CspSource::host_static("https://*.example.org"),
+    ]);
+    assert_eq!(
+        csp.value("").unwrap(),
+        "frame-ancestors 'self' https://partner.example.com https://*.example.org;"
+    );
+    assert_eq!(
+        ContentSecurityPolicy::new_empty()
+            .allow_framing_by([])
+            .value("")
+            .unwrap(),
+        "frame-ancestors 'self';"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn report_uri_validation() {
+    let csp = ContentSecurityPolicy::new_empty().default_src([CspSource::SelfOrigin]);
+    for uri in [
+        "https://example.com/csp-reports",
+        "/csp-reports?source=app",
+        "//reports.example.com/csp",
+        "http://[::1]:8080/csp",
+    ] {
+        let value = csp.clone().report_uri(uri).value("").unwrap();
+        assert_eq!(value, format!("default-src 'self';report-uri {uri};"));
+    }
+    for uri in [
+        "",
+        "https://example.com/csp; script-src *",
+        "https://example.com/a b",
+        "https://example.com/\u{e9}",
+        "example.com/csp",
+        "1http://example.com",
+        "https:",
+        "/csp\n",
+    ] {
+        assert!(csp.clone().report_uri(uri).value("").is_err(), "{uri:?}");
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn report_to_validation() {
+    let csp = ContentSecurityPolicy::new_empty().report_uri("/csp");
+    let value = csp.clone().report_to("csp-endpoint_1").value("").unwrap();
+    assert_eq!(value, "report-uri /csp;report-to csp-endpoint_1;");
+    for group in ["", "csp endpoint", "csp;script-src *", "gr\u{fc}ppe"] {
+        assert!(csp.clone().report_to(group).value("").is_err(), "{group:?}");
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn estimated_len_matches_generated_nonces() {
+    let nonce = crate::random_string(crate::NONCE_LENGTH);
+    for csp in [
+        ContentSecurityPolicy::strict_default(),
+        ContentSecurityPolicy::example_spa(),
+        ContentSecurityPolicy::new_empty(),
+    ] {
+        assert_eq!(csp.estimated_len(), csp.value(&nonce).unwrap().len());
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn nonce_in_frame_ancestors_is_invalid() {
+    let csp = ContentSecurityPolicy::new_empty()
+        .script_src([CspSource::Nonce])
+        .frame_ancestors([CspSource::SelfOrigin, CspSource::Nonce]);
+    assert_eq!(
+        csp.validate(),
+        [CspWarning::InvalidSourceInDirective {
+            directive: CspDirective::FrameAncestors,
+            source: CspSource::Nonce,
+        }]
+    );
+    assert!(csp
+        .frame_ancestors([CspSource::SelfOrigin])
+        .validate()
+        .is_empty());
+}
+
+#[cfg(test)]
+#[test]
+fn amp_policy_contains_required_sources() {
+    let value = ContentSecurityPolicy::for_amp().value("").unwrap();
+    let value = value.to_str().unwrap();
+    assert!(value.contains(
+        "script-src 'unsafe-inline' 'self' https://cdn.ampproject.org *.ampproject.org \
+         *.cdn.ampproject.org blob:;"
+    ));
+    assert!(value.contains("style-src 'unsafe-inline' 'self' https://cdn.ampproject.org;"));
+    assert!(value.contains("object-src 'none';"));
+}
+
+#[cfg(test)]
+#[test]
+fn parse_round_trips_strict_default() {
+    let csp = ContentSecurityPolicy::strict_default()
+        .script_src([CspSource::Nonce, CspSource::StrictDynamic])
+        .report_to("csp");
+    let value = csp.value("abc").unwrap();
+    let parsed = ContentSecurityPolicy::parse(value.to_str().unwrap()).unwrap();
+    assert!(parsed.semantically_eq(&csp));
+    assert_eq!(
+        ContentSecurityPolicy::parse("script-src 'bogus'"),
+        Err(CspParseError::UnknownKeyword("'bogus'".to_string()))
+    );
+    assert_eq!(
+        ContentSecurityPolicy::parse("scrpt-src 'self'"),
+        Err(CspParseError::UnknownDirective("scrpt-src".to_string()))
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn directive_introspection() {
+    let csp = ContentSecurityPolicy::strict_default();
+    assert!(csp.has_directive(CspDirective::ScriptSrc));
+    assert!(!csp.has_directive(CspDirective::WorkerSrc));
+    assert_eq!(csp.directive_source_count(CspDirective::FontSrc), 3);
+    assert_eq!(csp.directive_source_count(CspDirective::TrustedTypes), 0);
+    assert_eq!(csp.sources(CspDirective::ObjectSrc), [CspSource::None]);
+    assert!(csp.any_directive_contains(&CspSource::UnsafeInline));
+    assert!(!csp.any_directive_contains(&CspSource::Nonce));
+
+    let empty = ContentSecurityPolicy::new_empty();
+    assert!(!empty.has_directive(CspDirective::DefaultSrc));
+    assert!(!empty.any_directive_contains(&CspSource::SelfOrigin));
+    let trusted = empty.trusted_types([CspSource::TrustedTypeStar]);
+    assert!(trusted.has_directive(CspDirective::TrustedTypes));
+
+    assert_eq!(CspDirective::DefaultSrc.name(), "default-src");
+    assert_eq!(CspDirective::FrameAncestors.name(), "frame-ancestors");
+    assert_eq!(CspDirective::TrustedTypes.name(), "trusted-types");
+}
+
+#[cfg(test)]
+#[test]
+fn dev_relaxations() {
+    let csp = ContentSecurityPolicy::new_empty()
+        .default_src([CspSource::SelfOrigin])
+        .with_dev_relaxations();
+    assert_eq!(
+        csp.value("").unwrap(),
+        "default-src 'self';connect-src 'self' ws: wss:;script-src 'self' 'unsafe-eval';"
+    );
+    assert_eq!(csp.clone().with_dev_relaxations(), csp);
+}
+
+#[cfg(test)]
+#[test]
+fn schemes_keep_their_colon() {
+    use CspSchemeSource::*;
+
+    let all = [Data, Mediastream, Blob, Filesystem, Http, Https, Ws, Wss];
+    // fails to compile when a scheme is added, so it gets added to `all` too
+    for scheme in all {
+        match scheme {
+            Data | Mediastream | Blob | Filesystem | Http | Https | Ws | Wss => {}
+        }
+        let rendered = scheme.as_ref();
+        assert!(rendered.ends_with(':'), "{rendered}");
+        assert_eq!(rendered.matches(':').count(), 1, "{rendered}");
+    }
+
+    let csp = ContentSecurityPolicy::new_empty()
+        .connect_src([CspSchemeSource::Http.into(), CspSchemeSource::Https.into()]);
+    assert_eq!(
+        csp.value("").unwrap().as_bytes(),
+        b"connect-src http: https:;"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn effective_sources_follow_fallback_chains() {
+    let host = |h: &str| CspSource::Host(h.to_string().into());
+    let csp = ContentSecurityPolicy::new_empty()
+        .default_src([CspSource::SelfOrigin])
+        .script_src([host("scripts.example")])
+        .child_src([host("frames.example")])
+        .img_src([CspSource::None]);
+
+    // own sources win
+    assert_eq!(
+        csp.effective_sources_for(CspDirective::ImgSrc),
+        [CspSource::None]
+    );
+    // fetch directives fall back to default-src
+    assert_eq!(
+        csp.effective_sources_for(CspDirective::FontSrc),
+        [CspSource::SelfOrigin]
+    );
+    // -elem and -attr fall back to their parent first
+    assert_eq!(
+        csp.effective_sources_for(CspDirective::ScriptSrcElem),
+        [host("scripts.example")]
+    );
+    assert_eq!(
+        csp.effective_sources_for(CspDirective::StyleSrcAttr),
+        [CspSource::SelfOrigin]
+    );
+    // fenced-frame-src -> frame-src -> child-src
+    assert_eq!(
+        csp.effective_sources_for(CspDirective::FencedFrameSrc),
+        [host("frames.example")]
+    );
+    // worker-src -> child-src -> script-src -> default-src
+    assert_eq!(
+        csp.effective_sources_for(CspDirective::WorkerSrc),
+        [host("frames.example")]
+    );
+    let no_child = ContentSecurityPolicy {
+        child_src: vec![],
+        ..csp.clone()
+    };
+    assert_eq!(
+        no_child.effective_sources_for(CspDirective::WorkerSrc),
+        [host("scripts.example")]
+    );
+    // document and navigation directives never fall back
+    assert!(csp
+        .effective_sources_for(CspDirective::FormAction)
+        .is_empty());
+    assert!(csp.effective_sources_for(CspDirective::BaseUri).is_empty());
+
+    let effective = csp.effective_policy();
+    assert_eq!(effective.connect_src, [CspSource::SelfOrigin]);
+    assert_eq!(effective.img_src, [CspSource::None]);
+    assert_eq!(effective.script_src_attr, [host("scripts.example")]);
+    assert_eq!(effective.worker_src, [host("frames.example")]);
+    assert!(effective.frame_ancestors.is_empty());
+    assert_eq!(effective.effective_policy(), effective);
+}
+
+#[cfg(test)]
+#[test]
+fn locked_down_preset() {
+    let value = ContentSecurityPolicy::locked_down().value("").unwrap();
+    let value = value.to_str().unwrap();
+    for directive in [
+        "default-src 'none';",
+        "script-src 'self';",
+        "style-src 'self';",
+        "img-src 'self';",
+        "connect-src 'self';",
+    ] {
+        assert!(value.contains(directive), "{value}");
+    }
+    assert!(!value.contains("font-src"), "{value}");
+}
+
+#[cfg(test)]
+#[test]
+fn allow_data_images() {
+    let csp = ContentSecurityPolicy::locked_down().allow_data_images();
+    assert!(csp
+        .value("")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .contains("img-src 'self' data:;"));
+    // already allowed by the strict default, so nothing changes
+    let strict = ContentSecurityPolicy::strict_default();
+    assert_eq!(strict.clone().allow_data_images(), strict);
+
+    let csp = ContentSecurityPolicy::new_empty()
+        .default_src([CspSource::SelfOrigin])
+        .allow_data_images();
+    assert_eq!(
+        csp.value("").unwrap(),
+        "default-src 'self';img-src 'self' data:;"
+    );
+}
+
+#[cfg(all(test, debug_assertions))]
+#[test]
+#[should_panic = "empty nonce"]
+fn empty_nonce_is_rejected() {
+    let csp = ContentSecurityPolicy::new_empty().script_src([CspSource::Nonce]);
+    let _ = csp.value("");
+}
+
+#[cfg(test)]
+#[test]
+fn empty_nonce_is_fine_without_nonce_sources() {
+    let csp = ContentSecurityPolicy::strict_default();
+    assert_eq!(csp.value("").unwrap(), csp.value("unused").unwrap());
+}
+
+#[cfg(test)]
+#[test]
+fn security_levels() {
+    use CspSecurityLevel::*;
+
+    let level = |csp: ContentSecurityPolicy| csp.minimum_security_level();
+    let empty = ContentSecurityPolicy::new_empty;
+    let hash = CspSource::sha256("47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=").unwrap();
+
+    assert_eq!(level(empty()), None);
+    assert_eq!(level(empty().img_src([CspSource::SelfOrigin])), None);
+    assert_eq!(
+        level(empty().default_src([CspSource::SelfOrigin, CspSource::UnsafeInline])),
+        Basic
+    );
+    assert_eq!(
+        level(empty().script_src([
+            CspSource::SelfOrigin,
+            CspSource::UnsafeEval,
+            CspSource::Nonce
+        ])),
+        Basic
+    );
+    assert_eq!(level(ContentSecurityPolicy::strict_default()), Moderate);
+    assert_eq!(
+        level(empty().script_src([CspSource::SelfOrigin, hash])),
+        Strict
+    );
+    // ignored next to a nonce
+    assert_eq!(
+        level(empty().script_src([CspSource::Nonce, CspSource::UnsafeInline])),
+        Strict
+    );
+    assert_eq!(
+        level(empty().script_src([
+            CspSource::Nonce,
+            CspSource::StrictDynamic,
+            CspSource::UnsafeHashes
+        ])),
+        Strict
+    );
+    assert_eq!(
+        level(empty().script_src([CspSource::Nonce, CspSource::StrictDynamic])),
+        Paranoid
+    );
+    assert!(Paranoid > Strict && Strict > Moderate && Moderate > Basic && Basic > None);
+}
+
+#[cfg(test)]
+#[test]
+fn example_spa_serializes() {
+    let value = ContentSecurityPolicy::example_spa().value("n0nce").unwrap();
+    let value = value.to_str().unwrap();
+    for directive in [
+        "default-src 'self';",
+        "script-src 'nonce-n0nce' 'strict-dynamic';",
+        "connect-src 'self' https://api.example.com wss:;",
+        "img-src 'self' data: https://cdn.example.com;",
+        "object-src 'none';",
+    ] {
+        assert!(value.contains(directive), "{value}");
+    }
+}
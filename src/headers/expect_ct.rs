@@ -0,0 +1,66 @@
+#![allow(deprecated)]
+
+use http::{header::InvalidHeaderValue, HeaderName, HeaderValue};
+
+use crate::headers::Header;
+
+/// Only exists because some compliance checklists still look for it.
+#[deprecated(note = "Expect-CT is deprecated; Certificate Transparency is now mandatory")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExpectCt {
+    pub max_age: u32,
+    pub enforce: bool,
+    pub report_uri: Option<String>,
+}
+
+impl ExpectCt {
+    fn raw_value(&self) -> String {
+        let mut raw_header = format!("max-age={}", self.max_age);
+        if self.enforce {
+            raw_header.push_str(", enforce");
+        }
+        if let Some(report_uri) = &self.report_uri {
+            raw_header.push_str(&format!(", report-uri=\"{report_uri}\""));
+        }
+        raw_header
+    }
+
+    /// Like [`Header::value`], but returns an error instead of panicking.
+    pub(crate) fn try_value(&self) -> Result<HeaderValue, InvalidHeaderValue> {
+        HeaderValue::from_str(&self.raw_value())
+    }
+}
+
+impl Header for ExpectCt {
+    fn name(&self) -> HeaderName {
+        header_name!("expect-ct")
+    }
+
+    fn value(&self) -> HeaderValue {
+        let raw_header = self.raw_value();
+        match HeaderValue::from_str(&raw_header) {
+            Ok(val) => val,
+            Err(source) => {
+                panic!("Failed to convert Expect-CT string `{raw_header}` to header: `{source:?}`",);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn expect_ct_serialization() {
+    let mut expect_ct = ExpectCt {
+        max_age: 86400,
+        enforce: false,
+        report_uri: None,
+    };
+    assert_eq!(expect_ct.value(), "max-age=86400");
+    expect_ct.enforce = true;
+    assert_eq!(expect_ct.value(), "max-age=86400, enforce");
+    expect_ct.report_uri = Some("https://example.com/report".to_string());
+    assert_eq!(
+        expect_ct.value(),
+        "max-age=86400, enforce, report-uri=\"https://example.com/report\""
+    );
+}
@@ -15,23 +15,69 @@ macro_rules! header_name {
 }
 
 mod csp;
+mod expect_ct;
+mod permissions_policy;
+mod reporting_endpoints;
 mod sts;
 
-pub use csp::{ContentSecurityPolicy, CspHashAlgorithm, CspSchemeSource, CspSource};
+pub use csp::{
+    ContentSecurityPolicy, CspDirective, CspHashAlgorithm, CspParseError, CspSchemeSource,
+    CspSecurityLevel, CspSource, CspWarning, InvalidCspSource,
+};
+#[allow(deprecated)]
+pub use expect_ct::ExpectCt;
 use http::{
     header::{
         REFERRER_POLICY, X_CONTENT_TYPE_OPTIONS, X_DNS_PREFETCH_CONTROL, X_FRAME_OPTIONS,
         X_XSS_PROTECTION,
     },
+    response::Builder,
     HeaderName, HeaderValue,
 };
+pub use permissions_policy::{PermissionsOrigin, PermissionsPolicy};
+pub use reporting_endpoints::ReportingEndpoints;
 pub use sts::StrictTransportSecurity;
 
+use crate::Sombrero;
+
 pub trait Header {
     fn name(&self) -> HeaderName;
     fn value(&self) -> HeaderValue;
 }
 
+impl<T: Header> Header for &T {
+    fn name(&self) -> HeaderName {
+        T::name(self)
+    }
+
+    fn value(&self) -> HeaderValue {
+        T::value(self)
+    }
+}
+
+/// Apply security headers to responses built by hand with [`http::response::Builder`].
+pub trait ResponseBuilderExt {
+    /// Add a single security header.
+    #[must_use]
+    fn security_header(self, header: impl Header) -> Self;
+    /// Add every header `sombrero` is configured with, using `nonce` for the CSP.
+    #[must_use]
+    fn sombrero(self, sombrero: &Sombrero, nonce: &str) -> Self;
+}
+
+impl ResponseBuilderExt for Builder {
+    fn security_header(self, header: impl Header) -> Self {
+        self.header(header.name(), header.value())
+    }
+
+    fn sombrero(mut self, sombrero: &Sombrero, nonce: &str) -> Self {
+        if let Some(headers) = self.headers_mut() {
+            sombrero.insert_headers(headers, nonce);
+        }
+        self
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub enum CrossOriginEmbedderPolicy {
     #[default]
@@ -54,6 +100,17 @@ impl Header for CrossOriginEmbedderPolicy {
     }
 }
 
+impl CrossOriginEmbedderPolicy {
+    /// The header value, as a string.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::RequireCorp => "require-corp",
+            Self::Credentialless => "credentialless",
+            Self::UnsafeNone => "unsafe-none",
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub enum CrossOriginOpenerPolicy {
     #[default]
@@ -76,6 +133,17 @@ impl Header for CrossOriginOpenerPolicy {
     }
 }
 
+impl CrossOriginOpenerPolicy {
+    /// The header value, as a string.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::SameOrigin => "same-origin",
+            Self::SameOriginAllowPopups => "same-origin-allow-popups",
+            Self::UnsafeNone => "unsafe-none",
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub enum CrossOriginResourcePolicy {
     #[default]
@@ -98,6 +166,17 @@ impl Header for CrossOriginResourcePolicy {
     }
 }
 
+impl CrossOriginResourcePolicy {
+    /// The header value, as a string.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::SameOrigin => "same-origin",
+            Self::SameSite => "same-site",
+            Self::CrossOrigin => "cross-origin",
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub struct OriginAgentCluster;
 
@@ -152,6 +231,22 @@ impl Header for ReferrerPolicy {
     }
 }
 
+impl ReferrerPolicy {
+    /// The header value, as a string.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::NoReferrer => "no-referrer",
+            Self::NoReferrerWhenDowngrade => "no-referrer-when-downgrade",
+            Self::Origin => "origin",
+            Self::OriginWhenCrossOrigin => "origin-when-cross-origin",
+            Self::SameOrigin => "same-origin",
+            Self::StrictOrigin => "strict-origin",
+            Self::StrictOriginWhenCrossOrigin => "strict-origin-when-cross-origin",
+            Self::UnsafeUrl => "unsafe-url",
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub struct XContentTypeOptions;
 
@@ -165,6 +260,8 @@ impl Header for XContentTypeOptions {
     }
 }
 
+/// Whether browsers may look up the domains of links before they are followed. `Off` is more
+/// private, `On` makes navigation faster. Not sent by [`Sombrero::default`].
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub enum XDnsPrefetchControl {
     On,
@@ -185,6 +282,16 @@ impl Header for XDnsPrefetchControl {
     }
 }
 
+impl XDnsPrefetchControl {
+    /// The header value, as a string.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::On => "on",
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub struct XDownloadOptions;
 
@@ -218,6 +325,16 @@ impl Header for XFrameOptions {
     }
 }
 
+impl XFrameOptions {
+    /// The header value, as a string.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Deny => "DENY",
+            Self::Sameorigin => "SAMEORIGIN",
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub enum XPermittedCrossDomainPolicies {
     #[default]
@@ -242,6 +359,20 @@ impl Header for XPermittedCrossDomainPolicies {
     }
 }
 
+impl XPermittedCrossDomainPolicies {
+    /// The header value, as a string.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::MasterOnly => "master-only",
+            Self::ByContentType => "by-content-type",
+            Self::All => "all",
+        }
+    }
+}
+
+/// The XSS filter of old browsers. It is buggy and can be abused to leak data across origins,
+/// so [`Self::False`], sending `0`, is the default. The CSP does its job now.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub enum XXssProtection {
     #[default]
@@ -263,3 +394,69 @@ impl Header for XXssProtection {
         }
     }
 }
+
+impl XXssProtection {
+    /// The header value, as a string.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::TrueBlock => "1; mode=block",
+            Self::True => "1",
+            Self::False => "0",
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn as_str_matches_value() {
+    use CrossOriginEmbedderPolicy as Coep;
+    use CrossOriginOpenerPolicy as Coop;
+    use CrossOriginResourcePolicy as Corp;
+    use XPermittedCrossDomainPolicies as Xpcdp;
+    for h in [Coep::RequireCorp, Coep::Credentialless, Coep::UnsafeNone] {
+        assert_eq!(h.as_str(), h.value());
+    }
+    for h in [
+        Coop::SameOrigin,
+        Coop::SameOriginAllowPopups,
+        Coop::UnsafeNone,
+    ] {
+        assert_eq!(h.as_str(), h.value());
+    }
+    for h in [Corp::SameOrigin, Corp::SameSite, Corp::CrossOrigin] {
+        assert_eq!(h.as_str(), h.value());
+    }
+    for h in [
+        ReferrerPolicy::NoReferrer,
+        ReferrerPolicy::NoReferrerWhenDowngrade,
+        ReferrerPolicy::Origin,
+        ReferrerPolicy::OriginWhenCrossOrigin,
+        ReferrerPolicy::SameOrigin,
+        ReferrerPolicy::StrictOrigin,
+        ReferrerPolicy::StrictOriginWhenCrossOrigin,
+        ReferrerPolicy::UnsafeUrl,
+    ] {
+        assert_eq!(h.as_str(), h.value());
+    }
+    for h in [XDnsPrefetchControl::On, XDnsPrefetchControl::Off] {
+        assert_eq!(h.as_str(), h.value());
+    }
+    for h in [XFrameOptions::Deny, XFrameOptions::Sameorigin] {
+        assert_eq!(h.as_str(), h.value());
+    }
+    for h in [
+        Xpcdp::None,
+        Xpcdp::MasterOnly,
+        Xpcdp::ByContentType,
+        Xpcdp::All,
+    ] {
+        assert_eq!(h.as_str(), h.value());
+    }
+    for h in [
+        XXssProtection::False,
+        XXssProtection::TrueBlock,
+        XXssProtection::True,
+    ] {
+        assert_eq!(h.as_str(), h.value());
+    }
+}
@@ -0,0 +1,150 @@
+use http::{header::InvalidHeaderValue, HeaderName, HeaderValue};
+
+use crate::headers::Header;
+
+/// Who a feature is allowed for, in a [`PermissionsPolicy`] allowlist.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PermissionsOrigin {
+    /// `self`, the origin of the document.
+    SelfOrigin,
+    /// `*`, every origin. Must be the only entry of its allowlist.
+    Any,
+    /// A quoted origin, like `"https://example.com"`.
+    Origin(String),
+}
+
+impl PermissionsOrigin {
+    fn serialize(&self, s: &mut String) {
+        match self {
+            Self::SelfOrigin => s.push_str("self"),
+            Self::Any => s.push('*'),
+            Self::Origin(origin) => {
+                s.push('"');
+                s.push_str(origin);
+                s.push('"');
+            }
+        }
+    }
+}
+
+/// Controls which browser features a page and its frames may use.
+/// A feature with an empty allowlist is denied everywhere, and serializes as `feature=()`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct PermissionsPolicy {
+    pub features: Vec<(String, Vec<PermissionsOrigin>)>,
+}
+
+impl PermissionsPolicy {
+    /// Deny the powerful features most sites never use: sensors, cameras and microphones,
+    /// location, device access APIs, payments and interest-based advertising.
+    pub fn deny_all_sensitive() -> Self {
+        [
+            "accelerometer",
+            "ambient-light-sensor",
+            "bluetooth",
+            "browsing-topics",
+            "camera",
+            "display-capture",
+            "geolocation",
+            "gyroscope",
+            "hid",
+            "idle-detection",
+            "magnetometer",
+            "microphone",
+            "midi",
+            "payment",
+            "serial",
+            "usb",
+            "xr-spatial-tracking",
+        ]
+        .into_iter()
+        .fold(Self::default(), Self::deny)
+    }
+
+    /// Deny `feature` everywhere.
+    #[must_use]
+    pub fn deny(self, feature: &str) -> Self {
+        self.allow(feature, [])
+    }
+
+    /// Allow `feature` for `origins` only. Replaces any earlier allowlist for `feature`.
+    #[must_use]
+    pub fn allow(
+        mut self,
+        feature: &str,
+        origins: impl IntoIterator<Item = PermissionsOrigin>,
+    ) -> Self {
+        let origins = origins.into_iter().collect();
+        match self.features.iter_mut().find(|(name, _)| name == feature) {
+            Some((_, existing)) => *existing = origins,
+            None => self.features.push((feature.to_string(), origins)),
+        }
+        self
+    }
+}
+
+impl Header for PermissionsPolicy {
+    fn name(&self) -> HeaderName {
+        header_name!("permissions-policy")
+    }
+
+    fn value(&self) -> HeaderValue {
+        let raw_header = self.raw_value();
+        match HeaderValue::from_str(&raw_header) {
+            Ok(val) => val,
+            Err(source) => {
+                panic!("Failed to convert Permissions-Policy string `{raw_header}` to header: `{source:?}`");
+            }
+        }
+    }
+}
+
+impl PermissionsPolicy {
+    fn raw_value(&self) -> String {
+        let mut raw_header = String::new();
+        for (i, (feature, origins)) in self.features.iter().enumerate() {
+            if i != 0 {
+                raw_header.push_str(", ");
+            }
+            raw_header.push_str(feature);
+            raw_header.push('=');
+            if let [PermissionsOrigin::Any] = origins.as_slice() {
+                raw_header.push('*');
+                continue;
+            }
+            raw_header.push('(');
+            for (i, origin) in origins.iter().enumerate() {
+                if i != 0 {
+                    raw_header.push(' ');
+                }
+                origin.serialize(&mut raw_header);
+            }
+            raw_header.push(')');
+        }
+        raw_header
+    }
+
+    /// Like [`Header::value`], but returns an error instead of panicking.
+    pub(crate) fn try_value(&self) -> Result<HeaderValue, InvalidHeaderValue> {
+        HeaderValue::from_str(&self.raw_value())
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn permissions_policy_serialization() {
+    let policy = PermissionsPolicy::default()
+        .deny("camera")
+        .allow("fullscreen", [PermissionsOrigin::Any])
+        .allow(
+            "geolocation",
+            [
+                PermissionsOrigin::SelfOrigin,
+                PermissionsOrigin::Origin("https://maps.example".to_string()),
+            ],
+        );
+    assert_eq!(
+        policy.value(),
+        "camera=(), fullscreen=*, geolocation=(self \"https://maps.example\")"
+    );
+}
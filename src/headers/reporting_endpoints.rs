@@ -0,0 +1,68 @@
+use http::{header::InvalidHeaderValue, HeaderName, HeaderValue};
+
+use crate::headers::Header;
+
+/// Declares named endpoints for the Reporting API, which `report-to` directives refer to.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ReportingEndpoints {
+    pub endpoints: Vec<(String, String)>,
+}
+
+impl ReportingEndpoints {
+    /// Declare the group `group`, which sends its reports to `url`.
+    /// Redeclaring a group replaces its url.
+    #[must_use]
+    pub fn endpoint(mut self, group: impl Into<String>, url: impl Into<String>) -> Self {
+        let group = group.into();
+        let url = url.into();
+        match self.endpoints.iter_mut().find(|(name, _)| *name == group) {
+            Some((_, existing)) => *existing = url,
+            None => self.endpoints.push((group, url)),
+        }
+        self
+    }
+}
+
+impl ReportingEndpoints {
+    fn raw_value(&self) -> String {
+        self.endpoints
+            .iter()
+            .map(|(group, url)| format!("{group}=\"{url}\""))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Like [`Header::value`], but returns an error instead of panicking.
+    pub(crate) fn try_value(&self) -> Result<HeaderValue, InvalidHeaderValue> {
+        HeaderValue::from_str(&self.raw_value())
+    }
+}
+
+impl Header for ReportingEndpoints {
+    fn name(&self) -> HeaderName {
+        header_name!("reporting-endpoints")
+    }
+
+    fn value(&self) -> HeaderValue {
+        let raw_header = self.raw_value();
+        match HeaderValue::from_str(&raw_header) {
+            Ok(val) => val,
+            Err(source) => {
+                panic!("Failed to convert Reporting-Endpoints string `{raw_header}` to header: `{source:?}`");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn endpoint_replaces_group() {
+    let endpoints = ReportingEndpoints::default()
+        .endpoint("csp", "https://a.example/r")
+        .endpoint("nel", "https://b.example/r")
+        .endpoint("csp", "https://c.example/r");
+    assert_eq!(
+        endpoints.value(),
+        "csp=\"https://c.example/r\", nel=\"https://b.example/r\""
+    );
+}
@@ -5,7 +5,9 @@ use crate::headers::Header;
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct StrictTransportSecurity {
     pub include_sub_domains: bool,
-    pub max_age: usize,
+    /// In seconds. A `u64`, so the range is the same on every platform. Prefer
+    /// [`Self::max_age_secs`], which caps it at [`Self::MAX_MAX_AGE`].
+    pub max_age: u64,
 }
 
 impl StrictTransportSecurity {
@@ -13,10 +15,26 @@ impl StrictTransportSecurity {
         include_sub_domains: true,
         max_age: Self::STS_MAX_AGE,
     };
-    const STS_MAX_AGE: usize = 180 * 24 * 60 * 60;
+    /// The largest `max-age` worth sending, 2<sup>31</sup> seconds (about 68 years). HTTP says
+    /// larger delta-seconds values may be treated as this one, and some clients store them
+    /// in 32 bits, so anything above it is more likely to overflow than to last longer.
+    pub const MAX_MAX_AGE: u64 = 1 << 31;
+    const STS_MAX_AGE: u64 = 180 * 24 * 60 * 60;
 
     /// sets the TTL in seconds that this policy will be enforced
+    #[deprecated = "`usize` differs between platforms, use `max_age_secs`"]
     pub const fn max_age(self, max_age: usize) -> Self {
+        self.max_age_secs(max_age as u64)
+    }
+
+    /// Sets the TTL in seconds that this policy will be enforced, capped at
+    /// [`Self::MAX_MAX_AGE`]. A year (31536000) or two is typical.
+    pub const fn max_age_secs(self, max_age: u64) -> Self {
+        let max_age = if max_age > Self::MAX_MAX_AGE {
+            Self::MAX_MAX_AGE
+        } else {
+            max_age
+        };
         Self { max_age, ..self }
     }
 
@@ -78,3 +96,16 @@ fn sts_default_matches() {
     const DEFAULT: StrictTransportSecurity = StrictTransportSecurity::DEFAULT;
     assert_eq!(DEFAULT.raw_value(), DEFAULT.value());
 }
+
+#[cfg(test)]
+#[test]
+fn sts_max_age_secs() {
+    const TWO_YEARS: u64 = 2 * 365 * 24 * 60 * 60;
+    let sts = StrictTransportSecurity::DEFAULT.max_age_secs(TWO_YEARS);
+    assert_eq!(sts.max_age, TWO_YEARS);
+    assert_eq!(sts.value(), "max-age=63072000;includeSubDomains");
+
+    let capped = StrictTransportSecurity::DEFAULT.max_age_secs(u64::MAX);
+    assert_eq!(capped.max_age, StrictTransportSecurity::MAX_MAX_AGE);
+    assert_eq!(capped.value(), "max-age=2147483648;includeSubDomains");
+}
@@ -8,8 +8,27 @@
 
 #[cfg(feature = "axum")]
 mod axum;
+#[cfg(feature = "body-hash-injection")]
+mod body_hash;
+mod cell;
 pub mod csp;
+mod defaults;
+mod fail_open;
 pub mod headers;
+#[cfg(feature = "minijinja")]
+pub mod minijinja;
+mod modifier;
+pub mod observatory;
+mod pretty;
+mod reload;
+#[cfg(feature = "axum-routing")]
+mod routed;
+#[cfg(feature = "axum-extra")]
+mod secure_cookie;
+pub mod security_headers;
+pub mod strictness;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 #[cfg(test)]
 mod tests;
@@ -20,26 +39,73 @@ use std::{
     task::{Context, Poll},
 };
 
+use base64::{engine::general_purpose::STANDARD, Engine};
 use futures_util::future::BoxFuture;
 use http::{
-    header::{CONTENT_SECURITY_POLICY, CONTENT_SECURITY_POLICY_REPORT_ONLY},
-    HeaderMap, HeaderName, HeaderValue, Request, Response,
+    header::{
+        InvalidHeaderName, InvalidHeaderValue, ACCESS_CONTROL_ALLOW_ORIGIN,
+        ACCESS_CONTROL_EXPOSE_HEADERS, CACHE_CONTROL, CONTENT_SECURITY_POLICY,
+        CONTENT_SECURITY_POLICY_REPORT_ONLY, RETRY_AFTER, VARY,
+    },
+    HeaderMap, HeaderName, HeaderValue, Request, Response, StatusCode, Uri, Version,
 };
 use rand::{distr::Alphanumeric, Rng};
 use tower_layer::Layer;
 use tower_service::Service;
 
+#[cfg(feature = "body-hash-injection")]
+pub use crate::body_hash::{HashingCspMiddleware, InlineScriptHashService};
+#[cfg(feature = "axum-routing")]
+pub use crate::routed::{RoutedSombrero, RoutedSombreroBuilder, RoutedSombreroService};
+#[cfg(feature = "axum-extra")]
+pub use crate::secure_cookie::{SecureCookieService, SecureCookieSombreroLayer};
+pub use crate::{
+    cell::{SombreroCell, SombreroStatic},
+    defaults::SombreroDefaults,
+    fail_open::{ErrorResponseService, FailOpenLayer},
+    modifier::SombreroModifier,
+    pretty::SombreroPretty,
+    reload::{ReloadableSombrero, ReloadableSombreroHandle, ReloadableSombreroService},
+};
 use crate::{
-    csp::{CspNonce, BAD_CSP_MESSAGE},
+    csp::{
+        CspHashes, CspNonce, NonceGenerator, NonceSeed, OwaspCspWarning, BAD_CSP_MESSAGE,
+        FALLBACK_CSP,
+    },
     headers::{
         ContentSecurityPolicy, CrossOriginEmbedderPolicy, CrossOriginOpenerPolicy,
-        CrossOriginResourcePolicy, Header, OriginAgentCluster, ReferrerPolicy,
+        CrossOriginResourcePolicy, CspSchemeSource, CspSecurityLevel, CspSource, Header,
+        OriginAgentCluster, PermissionsPolicy, ReferrerPolicy, ReportingEndpoints,
         StrictTransportSecurity, XContentTypeOptions, XDnsPrefetchControl, XDownloadOptions,
         XFrameOptions, XPermittedCrossDomainPolicies, XXssProtection,
     },
 };
 
-#[derive(Debug, Clone)]
+/// A [`Layer`] adding security headers to every response.
+///
+/// `Sombrero` is a plain layer, so it works with [`tower::util::BoxLayer`] when composing
+/// middleware stacks dynamically:
+///
+/// ```rust
+/// # use std::convert::Infallible;
+/// # use http::{Request, Response};
+/// # use tower::{util::BoxLayer, Layer};
+/// # use tower_sombrero::Sombrero;
+/// let layer: BoxLayer<_, Request<String>, Response<String>, Infallible> =
+///     BoxLayer::new(Sombrero::default());
+/// let service = layer.layer(tower::service_fn(|_req: Request<String>| async {
+///     Ok::<_, Infallible>(Response::new(String::new()))
+/// }));
+/// ```
+///
+/// Headers are added once the inner service has produced its response, so they also land on
+/// early responses from inner middleware. If the inner service fails, there is no response to
+/// add them to, and the error is passed on as is; see [`Sombrero::fail_open`] to change that.
+///
+/// Headers the response doesn't have yet are added in the order of [`Sombrero::insert_order`].
+/// That order is part of the public API, and only changes in breaking releases.
+///
+/// [`tower::util::BoxLayer`]: https://docs.rs/tower/latest/tower/util/struct.BoxLayer.html
 // would be Copy, if not for those meddling CSP strings
 pub struct Sombrero {
     content_security_policy: Option<Arc<ContentSecurityPolicy>>,
@@ -58,8 +124,102 @@ pub struct Sombrero {
     x_permitted_cross_domain_policies: Option<XPermittedCrossDomainPolicies>,
     /// Always use protection. Except X-XSS-Protection, which is buggy and can modify running scripts.
     x_xss_protection: Option<XXssProtection>,
+    #[allow(deprecated)]
+    expect_ct: Option<headers::ExpectCt>,
+    reporting_endpoints: Option<ReportingEndpoints>,
+    permissions_policy: Option<PermissionsPolicy>,
+    skip_csp_on_rate_limit: bool,
+    expose_csp_header: bool,
+    no_cache_with_nonce: bool,
+    no_store: bool,
+    respect_existing_csp: bool,
+    strip_downstream_csp: bool,
+    missing_only: bool,
+    strip_for_http10_clients: bool,
+    nonce_generator: Option<Arc<dyn NonceGenerator>>,
+    csp_header_name: HeaderName,
+    cross_origin_resource_policy_overrides: Vec<(PathPredicate, CrossOriginResourcePolicy)>,
+}
+
+/// Cheap: the policies and the nonce generator are behind [`Arc`]s, so clones share them
+/// instead of copying every source list. Only the few small `Vec`s are copied.
+impl Clone for Sombrero {
+    #[allow(deprecated)]
+    fn clone(&self) -> Self {
+        // destructured so that new fields can't be forgotten here
+        let Self {
+            content_security_policy,
+            content_security_policy_report_only,
+            cross_origin_embedder_policy,
+            cross_origin_opener_policy,
+            cross_origin_resource_policy,
+            origin_agent_cluster,
+            referrer_policy,
+            strict_transport_security,
+            x_content_type_options,
+            x_dns_prefetch_control,
+            x_download_options,
+            x_frame_options,
+            x_permitted_cross_domain_policies,
+            x_xss_protection,
+            expect_ct,
+            reporting_endpoints,
+            permissions_policy,
+            skip_csp_on_rate_limit,
+            expose_csp_header,
+            no_cache_with_nonce,
+            no_store,
+            respect_existing_csp,
+            strip_downstream_csp,
+            missing_only,
+            strip_for_http10_clients,
+            nonce_generator,
+            csp_header_name,
+            cross_origin_resource_policy_overrides,
+        } = self;
+        Self {
+            // shared, see `Self::edit_csp` for how they are changed
+            content_security_policy: content_security_policy.as_ref().map(Arc::clone),
+            content_security_policy_report_only: content_security_policy_report_only
+                .as_ref()
+                .map(Arc::clone),
+            nonce_generator: nonce_generator.as_ref().map(Arc::clone),
+            // everything else is `Copy` or small
+            cross_origin_embedder_policy: *cross_origin_embedder_policy,
+            cross_origin_opener_policy: *cross_origin_opener_policy,
+            cross_origin_resource_policy: *cross_origin_resource_policy,
+            origin_agent_cluster: *origin_agent_cluster,
+            referrer_policy: *referrer_policy,
+            strict_transport_security: *strict_transport_security,
+            x_content_type_options: *x_content_type_options,
+            x_dns_prefetch_control: *x_dns_prefetch_control,
+            x_download_options: *x_download_options,
+            x_frame_options: *x_frame_options,
+            x_permitted_cross_domain_policies: *x_permitted_cross_domain_policies,
+            x_xss_protection: *x_xss_protection,
+            expect_ct: expect_ct.clone(),
+            reporting_endpoints: reporting_endpoints.clone(),
+            permissions_policy: permissions_policy.clone(),
+            skip_csp_on_rate_limit: *skip_csp_on_rate_limit,
+            expose_csp_header: *expose_csp_header,
+            no_cache_with_nonce: *no_cache_with_nonce,
+            no_store: *no_store,
+            respect_existing_csp: *respect_existing_csp,
+            strip_downstream_csp: *strip_downstream_csp,
+            missing_only: *missing_only,
+            strip_for_http10_clients: *strip_for_http10_clients,
+            csp_header_name: csp_header_name.clone(),
+            cross_origin_resource_policy_overrides: cross_origin_resource_policy_overrides.clone(),
+        }
+    }
 }
 
+/// Decides whether a request path gets an override.
+///
+/// Used by [`Sombrero::cross_origin_resource_policy_when`]. Closures that don't capture anything
+/// coerce to this, as in `|path| path.starts_with("/assets/")`.
+pub type PathPredicate = fn(&str) -> bool;
+
 macro_rules! builder_add {
     ($field:ident, $kind:ty) => {
         #[must_use]
@@ -113,6 +273,20 @@ impl Sombrero {
             x_frame_options: None,
             x_permitted_cross_domain_policies: None,
             x_xss_protection: None,
+            expect_ct: None,
+            reporting_endpoints: None,
+            permissions_policy: None,
+            skip_csp_on_rate_limit: false,
+            expose_csp_header: false,
+            no_cache_with_nonce: false,
+            no_store: false,
+            respect_existing_csp: false,
+            strip_downstream_csp: false,
+            missing_only: false,
+            nonce_generator: None,
+            strip_for_http10_clients: false,
+            csp_header_name: CONTENT_SECURITY_POLICY,
+            cross_origin_resource_policy_overrides: Vec::new(),
         }
     }
 }
@@ -133,6 +307,8 @@ impl Sombrero {
     builder_remove!(x_frame_options, remove_x_frame_options);
     builder_remove!(x_permitted_cross_domain_policies, remove_x_permitted_cross_domain_policies);
     builder_remove!(x_xss_protection, remove_x_xss_protection);
+    builder_remove!(reporting_endpoints, remove_reporting_endpoints);
+    builder_remove!(permissions_policy, remove_permissions_policy);
     builder_add_arc!(content_security_policy, ContentSecurityPolicy);
     builder_add_arc!(content_security_policy_report_only, ContentSecurityPolicy);
     builder_add!(cross_origin_embedder_policy, CrossOriginEmbedderPolicy);
@@ -147,6 +323,806 @@ impl Sombrero {
     builder_add!(x_frame_options, XFrameOptions);
     builder_add!(x_permitted_cross_domain_policies, XPermittedCrossDomainPolicies);
     builder_add!(x_xss_protection, XXssProtection);
+    builder_add!(reporting_endpoints, ReportingEndpoints);
+    builder_add!(permissions_policy, PermissionsPolicy);
+}
+
+#[allow(deprecated)]
+impl Sombrero {
+    #[deprecated(note = "Expect-CT is deprecated; Certificate Transparency is now mandatory")]
+    #[must_use]
+    pub fn expect_ct(self, k: headers::ExpectCt) -> Self {
+        Self {
+            expect_ct: Some(k),
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn remove_expect_ct(self) -> Self {
+        Self {
+            expect_ct: None,
+            ..self
+        }
+    }
+}
+
+impl Sombrero {
+    /// The enforced content security policy, if one is configured.
+    pub fn csp(&self) -> Option<&ContentSecurityPolicy> {
+        self.content_security_policy.as_deref()
+    }
+
+    /// The report-only content security policy, if one is configured.
+    pub fn csp_report_only(&self) -> Option<&ContentSecurityPolicy> {
+        self.content_security_policy_report_only.as_deref()
+    }
+
+    /// Leak this config, for layering by reference for the rest of the program, as in
+    /// `router.layer(sombrero.into_static())`. Only meant for configs built once at startup.
+    /// For a `static`, see [`SombreroStatic`].
+    pub fn into_static(self) -> &'static Self {
+        Box::leak(Box::new(self))
+    }
+
+    /// Put this config behind an [`Arc`], for sharing it between threads or tasks without
+    /// cloning even the few fields that [`Clone`] copies. `&Sombrero` is a [`Layer`] too, so
+    /// `&*shared` can be used as one.
+    pub fn share(self) -> Arc<Self> {
+        Arc::new(self)
+    }
+
+    /// Modify the enforced content security policy with `f`.
+    /// If there is none, `f` is given [`ContentSecurityPolicy::new_empty`].
+    #[must_use]
+    pub fn edit_csp(self, f: impl FnOnce(ContentSecurityPolicy) -> ContentSecurityPolicy) -> Self {
+        let csp = self
+            .content_security_policy
+            .map_or_else(ContentSecurityPolicy::new_empty, Arc::unwrap_or_clone);
+        Self {
+            content_security_policy: Some(Arc::new(f(csp))),
+            ..self
+        }
+    }
+
+    /// Only allow forms to submit to this origin, so injected forms can't post data to other domains.
+    /// Creates a policy with only `form-action` if there is none yet.
+    #[must_use]
+    pub fn form_action_self_only(self) -> Self {
+        self.edit_csp(|csp| csp.form_action(CspSource::SelfOrigin))
+    }
+
+    /// Like [`Self::form_action_self_only`], but also allow posting to `origins`.
+    #[must_use]
+    pub fn form_action_allow(self, origins: &[&str]) -> Self {
+        let mut form_action = vec![CspSource::SelfOrigin];
+        form_action.extend(
+            origins
+                .iter()
+                .map(|origin| CspSource::Host((*origin).to_string().into())),
+        );
+        self.edit_csp(|csp| csp.form_action(form_action))
+    }
+
+    /// The number of bytes the headers of this config take up, counting each name and value.
+    /// Headers added by other layers are not included. Responses whose headers exceed 8 KiB in
+    /// total are logged at the `warn` level, as CDNs commonly reject them.
+    pub fn total_header_byte_length(&self, nonce: &str) -> usize {
+        let mut m = HeaderMap::new();
+        self.insert_headers(&mut m, nonce);
+        header_byte_length(&m)
+    }
+
+    /// Whether the headers of this config alone take up more than `budget_bytes`.
+    pub fn exceeds_header_budget(&self, nonce: &str, budget_bytes: usize) -> bool {
+        self.total_header_byte_length(nonce) > budget_bytes
+    }
+
+    /// The size of each header of this config, largest first.
+    pub fn header_budget_report(&self, nonce: &str) -> Vec<(HeaderName, usize)> {
+        let mut m = HeaderMap::new();
+        self.insert_headers(&mut m, nonce);
+        let mut report: Vec<(HeaderName, usize)> = m
+            .iter()
+            .map(|(name, value)| (name.clone(), name.as_str().len() + value.len()))
+            .collect();
+        report.sort_by(|(_, a), (_, b)| b.cmp(a));
+        report
+    }
+
+    /// The headers this config adds, in the order they are inserted: the CSP headers first,
+    /// then the other headers in the order of the builder methods, then `Cache-Control`.
+    ///
+    /// Headers the response already has keep their position when replaced, and rate-limited
+    /// responses only get a subset (see [`Self::skip_csp_on_rate_limit`]).
+    #[allow(deprecated)]
+    pub fn insert_order(&self) -> Vec<HeaderName> {
+        let enforced = self.content_security_policy.is_some();
+        let mut order = Vec::new();
+        if enforced && self.expose_csp_header {
+            order.push(ACCESS_CONTROL_EXPOSE_HEADERS);
+        }
+        if enforced {
+            order.push(self.csp_header_name.clone());
+        }
+        if self.content_security_policy_report_only.is_some() {
+            order.push(CONTENT_SECURITY_POLICY_REPORT_ONLY);
+        }
+        let names = [
+            self.cross_origin_embedder_policy.map(|h| h.name()),
+            self.cross_origin_opener_policy.map(|h| h.name()),
+            self.cross_origin_resource_policy.map(|h| h.name()),
+            self.origin_agent_cluster.map(|h| h.name()),
+            self.referrer_policy.map(|h| h.name()),
+            self.strict_transport_security.map(|h| h.name()),
+            self.x_content_type_options.map(|h| h.name()),
+            self.x_dns_prefetch_control.map(|h| h.name()),
+            self.x_download_options.map(|h| h.name()),
+            self.x_frame_options.map(|h| h.name()),
+            self.x_permitted_cross_domain_policies.map(|h| h.name()),
+            self.x_xss_protection.map(|h| h.name()),
+            self.expect_ct.as_ref().map(Header::name),
+            self.reporting_endpoints.as_ref().map(Header::name),
+            self.permissions_policy.as_ref().map(Header::name),
+        ];
+        order.extend(names.into_iter().flatten());
+        if self.sends_no_store() {
+            order.push(CACHE_CONTROL);
+        }
+        order
+    }
+
+    /// Whether responses get `Cache-Control: no-store`, from [`Self::no_store`] or
+    /// [`Self::no_cache_with_nonce`].
+    pub(crate) fn sends_no_store(&self) -> bool {
+        self.no_store || (self.no_cache_with_nonce && self.uses_nonce())
+    }
+
+    /// Whether responses get `Access-Control-Expose-Headers`, see [`Self::expose_csp_header`].
+    pub(crate) const fn sends_exposed_csp(&self) -> bool {
+        self.expose_csp_header && self.content_security_policy.is_some()
+    }
+
+    /// Use `policy` as the `Cross-Origin-Resource-Policy` of requests whose path matches
+    /// `predicate`, for example to let other origins embed static assets. Only CORP is affected.
+    /// Overrides are checked in the order they were added, and the first match wins.
+    #[must_use]
+    pub fn cross_origin_resource_policy_when(
+        mut self,
+        predicate: PathPredicate,
+        policy: CrossOriginResourcePolicy,
+    ) -> Self {
+        self.cross_origin_resource_policy_overrides
+            .push((predicate, policy));
+        self
+    }
+
+    fn cross_origin_resource_policy_override(
+        &self,
+        path: &str,
+    ) -> Option<CrossOriginResourcePolicy> {
+        self.cross_origin_resource_policy_overrides
+            .iter()
+            .find(|(predicate, _)| predicate(path))
+            .map(|(_, policy)| *policy)
+    }
+
+    /// Set `X-Frame-Options` and CSP `frame-ancestors` together, so they can't disagree.
+    /// If there is no CSP yet, one containing only `frame-ancestors` is created.
+    /// To only set the CSP, see [`ContentSecurityPolicy::allow_framing_by`].
+    #[must_use]
+    pub fn frame_protection(self, protection: FrameProtection) -> Self {
+        let (x_frame_options, frame_ancestors) = match protection {
+            FrameProtection::DenyAll => (Some(XFrameOptions::Deny), vec![CspSource::None]),
+            FrameProtection::SameOrigin => {
+                (Some(XFrameOptions::Sameorigin), vec![CspSource::SelfOrigin])
+            }
+            FrameProtection::AllowOrigins(origins) => (
+                None,
+                origins
+                    .into_iter()
+                    .map(|origin| CspSource::Host(origin.into()))
+                    .collect(),
+            ),
+        };
+        Self {
+            x_frame_options,
+            ..self.edit_csp(|csp| csp.frame_ancestors(frame_ancestors))
+        }
+    }
+
+    /// Deny sensitive browser features with [`PermissionsPolicy::deny_all_sensitive`].
+    #[must_use]
+    pub fn with_strict_permissions_policy(self) -> Self {
+        self.permissions_policy(PermissionsPolicy::deny_all_sensitive())
+    }
+
+    /// Allow same-origin service workers, by adding `'self'` to `worker-src` and `fetch-src`.
+    ///
+    /// Browsers send `Service-Worker-Navigation-Preload` on preloaded navigation requests.
+    /// If responses depend on it, remember to add it to `Vary`; no response header is needed.
+    #[must_use]
+    pub fn with_service_worker_support(self) -> Self {
+        self.edit_csp(|mut csp| {
+            for sources in [&mut csp.worker_src, &mut csp.fetch_src] {
+                if !sources.contains(&CspSource::SelfOrigin) {
+                    sources.push(CspSource::SelfOrigin);
+                }
+            }
+            csp
+        })
+    }
+
+    /// Send CSP violation reports to `endpoint_url`, under the reporting group `group`.
+    ///
+    /// This sets `report-to` on the CSP and declares the group in `Reporting-Endpoints` at once,
+    /// so the two can't disagree. Other declared groups are kept. A report-only policy gets
+    /// `report-to` as well, and if it is the only policy, no enforced one is created.
+    #[must_use]
+    pub fn with_reporting(mut self, group: &str, endpoint_url: &str) -> Self {
+        let endpoints = self
+            .reporting_endpoints
+            .clone()
+            .unwrap_or_default()
+            .endpoint(group, endpoint_url);
+        if let Some(report_only) = self.content_security_policy_report_only.take() {
+            let report_only = Arc::unwrap_or_clone(report_only).report_to(group);
+            self = self.content_security_policy_report_only(report_only);
+            if self.content_security_policy.is_none() {
+                return self.reporting_endpoints(endpoints);
+            }
+        }
+        self.edit_csp(|csp| csp.report_to(group))
+            .reporting_endpoints(endpoints)
+    }
+
+    /// When enabled, rate-limited responses (429 or 503 with a `Retry-After` header) only get
+    /// `Strict-Transport-Security` and `X-Content-Type-Options`, and the CSP is never serialized.
+    #[must_use]
+    pub fn skip_csp_on_rate_limit(self, enabled: bool) -> Self {
+        Self {
+            skip_csp_on_rate_limit: enabled,
+            ..self
+        }
+    }
+
+    /// When enabled, the name of the enforced CSP header is appended to
+    /// `Access-Control-Expose-Headers`, so cross-origin scripts can read the policy for debugging.
+    #[must_use]
+    pub fn expose_csp_header(self, enabled: bool) -> Self {
+        Self {
+            expose_csp_header: enabled,
+            ..self
+        }
+    }
+
+    /// Send `Cache-Control: no-store` whenever a policy uses [`CspSource::Nonce`], replacing any
+    /// `Cache-Control` set by the handler. Cached pages would embed a nonce that no longer matches.
+    /// To keep caching with revalidation instead, see [`csp::NonceCacheLayer`].
+    #[must_use]
+    pub fn no_cache_with_nonce(self) -> Self {
+        Self {
+            no_cache_with_nonce: true,
+            ..self
+        }
+    }
+
+    /// When enabled, responses to `HTTP/1.0` requests get no headers at all. Clients that old
+    /// don't understand any of them, so they would be wasted bytes.
+    #[must_use]
+    pub fn strip_for_http10_clients(self, enabled: bool) -> Self {
+        Self {
+            strip_for_http10_clients: enabled,
+            ..self
+        }
+    }
+
+    /// Make nonces with `generator` instead of the built-in random generator.
+    #[must_use]
+    pub fn nonce_generator(self, generator: impl NonceGenerator + 'static) -> Self {
+        Self {
+            nonce_generator: Some(Arc::new(generator)),
+            ..self
+        }
+    }
+
+    /// When enabled, policies already on the response, such as from a [`CspLayer`](csp::CspLayer)
+    /// further in, are kept instead of being replaced by this config's.
+    ///
+    /// Either way, a response never ends up with two `Content-Security-Policy` headers.
+    /// With [`Self::strip_downstream_csp`], enforced policies are removed before this applies.
+    #[must_use]
+    pub fn respect_existing_csp(self, enabled: bool) -> Self {
+        Self {
+            respect_existing_csp: enabled,
+            ..self
+        }
+    }
+
+    /// When enabled, enforced policies set further in, such as by a handler or a
+    /// [`CspLayer`](csp::CspLayer), are removed from responses, while report-only ones are kept.
+    /// Combined with only a report-only policy here, nothing is enforced by any layer, but
+    /// violations are still reported, for seeing what a policy would block before rolling it out.
+    ///
+    /// Stripping happens first, so with [`Self::respect_existing_csp`] only downstream
+    /// report-only policies are kept. An enforced policy configured here is still sent.
+    #[must_use]
+    pub fn strip_downstream_csp(self, enabled: bool) -> Self {
+        Self {
+            strip_downstream_csp: enabled,
+            ..self
+        }
+    }
+
+    /// Only add headers the response doesn't have yet, as a safety net behind proxies or
+    /// handlers that set some of them for certain paths. Normally, configured headers replace
+    /// existing ones. See [`Self::apply_missing_only`] for applying this once.
+    #[must_use]
+    pub fn missing_only_mode(self) -> Self {
+        Self {
+            missing_only: true,
+            ..self
+        }
+    }
+
+    /// `Secure` cookies are only sent over HTTPS, so sites using them must make sure clients
+    /// stay on HTTPS. When enabled, this adds [`StrictTransportSecurity::DEFAULT`] if no
+    /// `Strict-Transport-Security` is configured yet. An existing policy is kept as is.
+    #[must_use]
+    pub fn enforce_secure_cookies_with_sts(self, enabled: bool) -> Self {
+        if enabled && self.strict_transport_security.is_none() {
+            self.strict_transport_security(StrictTransportSecurity::DEFAULT)
+        } else {
+            self
+        }
+    }
+
+    /// When enabled, every response gets `Cache-Control: no-store`, replacing any
+    /// `Cache-Control` set by the handler.
+    #[must_use]
+    pub fn no_store(self, enabled: bool) -> Self {
+        Self {
+            no_store: enabled,
+            ..self
+        }
+    }
+
+    /// Adjust this config for a server-sent events endpoint. Event streams aren't documents, so
+    /// there is no CSP, and they must not be cached, so `Cache-Control: no-store` is sent.
+    /// `X-Content-Type-Options` and `Cross-Origin-Resource-Policy: same-origin` are always set.
+    ///
+    /// Headers are added as soon as the handler returns its response, so streaming bodies are
+    /// never buffered.
+    #[must_use]
+    pub fn for_server_sent_events(self) -> Self {
+        self.remove_content_security_policy()
+            .remove_content_security_policy_report_only()
+            .x_content_type_options(XContentTypeOptions)
+            .cross_origin_resource_policy(CrossOriginResourcePolicy::SameOrigin)
+            .no_store(true)
+    }
+
+    /// Settings for running behind a TLS-terminating proxy that tunnels responses to
+    /// cross-origin tooling. Currently this is just [`Self::expose_csp_header`].
+    #[must_use]
+    pub fn proxy_forwarding_mode(self, enabled: bool) -> Self {
+        self.expose_csp_header(enabled)
+    }
+
+    /// Send the enforced CSP under `name` instead of `Content-Security-Policy`, for proxies that
+    /// expect it elsewhere. The report-only policy keeps `Content-Security-Policy-Report-Only`.
+    /// The name is parsed once, here; responses share it instead of parsing it again.
+    ///
+    /// # Errors
+    /// If `name` is not a valid header name.
+    pub fn csp_header_name(self, name: &str) -> Result<Self, InvalidHeaderName> {
+        Ok(Self {
+            csp_header_name: HeaderName::try_from(name)?,
+            ..self
+        })
+    }
+
+    /// Insert every configured header into `m`, using `nonce` for the content security policies.
+    /// A policy that fails to serialize is replaced by one blocking everything.
+    pub(crate) fn insert_headers(&self, m: &mut HeaderMap, nonce: &str) {
+        if let Err(error) = self.insert_headers_with_hashes(m, nonce, &[]) {
+            tracing::error!(%error, "{BAD_CSP_MESSAGE}");
+        }
+    }
+
+    /// [`Self::insert_headers`], with `hashes` added to `script-src` of both policies.
+    fn insert_headers_with_hashes(
+        &self,
+        m: &mut HeaderMap,
+        nonce: &str,
+        hashes: &[CspSource],
+    ) -> Result<(), InvalidHeaderValue> {
+        let result = self.insert_csp_headers(m, nonce, hashes);
+        self.insert_static_headers(m);
+        result
+    }
+
+    /// Insert the headers that depend on the nonce. A policy that fails to serialize is
+    /// replaced by [`FALLBACK_CSP`], and the error returned once everything is inserted.
+    fn insert_csp_headers(
+        &self,
+        m: &mut HeaderMap,
+        nonce: &str,
+        hashes: &[CspSource],
+    ) -> Result<(), InvalidHeaderValue> {
+        if self.strip_downstream_csp {
+            self.remove_enforced_csp(m);
+        }
+        let mut error = None;
+        let mut serialize = |csp: &ContentSecurityPolicy| {
+            csp.value_with_script_src(nonce, hashes)
+                .unwrap_or_else(|e| {
+                    error = Some(e);
+                    FALLBACK_CSP
+                })
+        };
+        let mut content_security_policy =
+            self.content_security_policy.as_deref().map(&mut serialize);
+        let mut content_security_policy_report_only = self
+            .content_security_policy_report_only
+            .as_deref()
+            .map(&mut serialize);
+        if self.respect_existing_csp {
+            let existing = |name: &HeaderName| m.contains_key(name);
+            if existing(&self.csp_header_name) {
+                content_security_policy = None;
+            }
+            if existing(&CONTENT_SECURITY_POLICY_REPORT_ONLY) {
+                content_security_policy_report_only = None;
+            }
+        }
+        if self.expose_csp_header && content_security_policy.is_some() {
+            m.append(
+                ACCESS_CONTROL_EXPOSE_HEADERS,
+                HeaderValue::from(self.csp_header_name.clone()),
+            );
+        }
+        add_opt_header_raw(m, self.csp_header_name.clone(), content_security_policy);
+        add_opt_header_raw(
+            m,
+            CONTENT_SECURITY_POLICY_REPORT_ONLY,
+            content_security_policy_report_only,
+        );
+        error.map_or(Ok(()), Err)
+    }
+
+    /// Remove enforced policies, under both the configured and the standard name.
+    fn remove_enforced_csp(&self, m: &mut HeaderMap) {
+        m.remove(&self.csp_header_name);
+        m.remove(CONTENT_SECURITY_POLICY);
+    }
+
+    /// Insert the headers that are the same for every request.
+    fn insert_static_headers(&self, m: &mut HeaderMap) {
+        add_opt_header(m, self.cross_origin_embedder_policy);
+        add_opt_header(m, self.cross_origin_opener_policy);
+        add_opt_header(m, self.cross_origin_resource_policy);
+        add_opt_header(m, self.origin_agent_cluster);
+        add_opt_header(m, self.referrer_policy);
+        add_opt_header(m, self.strict_transport_security);
+        add_opt_header(m, self.x_content_type_options);
+        add_opt_header(m, self.x_dns_prefetch_control);
+        add_opt_header(m, self.x_download_options);
+        add_opt_header(m, self.x_frame_options);
+        add_opt_header(m, self.x_permitted_cross_domain_policies);
+        add_opt_header(m, self.x_xss_protection);
+        add_opt_header(m, self.expect_ct.as_ref());
+        add_opt_header_append(m, self.reporting_endpoints.as_ref());
+        add_opt_header(m, self.permissions_policy.as_ref());
+        if self.sends_no_store() {
+            m.insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        }
+    }
+
+    /// Apply every configured header to the `headers` of `parts`, using `nonce` for the content
+    /// security policies. Useful in hyper services, which often work with split responses.
+    pub fn apply_to_parts(&self, parts: &mut http::response::Parts, nonce: &str) {
+        self.insert_headers(&mut parts.headers, nonce);
+    }
+
+    /// Apply every configured header to `response`, using `nonce` for the content security
+    /// policies. For when the response is only borrowed, as in hooks of other frameworks.
+    pub fn apply_to_response_ref<B>(&self, response: &mut Response<B>, nonce: &str) {
+        self.insert_headers(response.headers_mut(), nonce);
+    }
+
+    /// Like [`Self::apply_to_response_ref`], with `nonce` base64-encoded as the nonce. Nothing
+    /// random or thread-local is used, so the caller picks the entropy source, as in
+    /// environments where `rand`'s thread-local generator is unavailable, and the same bytes
+    /// always produce the same headers.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if `nonce` is empty and a policy uses [`CspSource::Nonce`].
+    pub fn apply_headers_deterministic<B>(&self, response: &mut Response<B>, nonce: &[u8]) {
+        let nonce = STANDARD.encode(nonce);
+        self.insert_headers(response.headers_mut(), &nonce);
+    }
+
+    /// Like [`Self::apply_to_response_ref`], but headers already on `response` are kept,
+    /// and only the missing ones are added, whether or not [`Self::missing_only_mode`] is set.
+    pub fn apply_missing_only<B>(&self, response: &mut Response<B>, nonce: &str) {
+        let mut headers = HeaderMap::new();
+        self.insert_headers(&mut headers, nonce);
+        insert_missing(response.headers_mut(), &headers);
+    }
+
+    /// Apply the headers that matter for a sub-resource, such as an HTTP/2 pushed asset,
+    /// rather than a document.
+    ///
+    /// Only `X-Content-Type-Options`, `Strict-Transport-Security` and
+    /// `Cross-Origin-Resource-Policy` are applied. Document-level headers like the CSP,
+    /// `X-Frame-Options` or `Referrer-Policy` have no effect on sub-resources, so no nonce
+    /// is needed. Caching is left to whatever sets `Cache-Control` for the asset.
+    pub fn apply_to_pushed_resource<B>(&self, response: &mut Response<B>) {
+        let m = response.headers_mut();
+        add_opt_header(m, self.x_content_type_options);
+        add_opt_header(m, self.strict_transport_security);
+        add_opt_header(m, self.cross_origin_resource_policy);
+    }
+
+    /// Check the enforced content security policy against the OWASP recommendations.
+    /// The report-only policy is ignored, as it does not protect anything.
+    /// No enforced policy at all fails every check that an empty policy would.
+    pub fn owasp_conformance_check(&self) -> Vec<OwaspCspWarning> {
+        self.content_security_policy.as_deref().map_or_else(
+            || OwaspCspWarning::check(&ContentSecurityPolicy::new_empty()),
+            OwaspCspWarning::check,
+        )
+    }
+
+    /// [`Self::default`], with [`ContentSecurityPolicy::for_saml_sso`] and
+    /// [`XFrameOptions::Deny`], as SAML pages should never be embedded.
+    pub fn for_saml_sso(idp_url: &str) -> Self {
+        Self::default()
+            .content_security_policy(ContentSecurityPolicy::for_saml_sso(idp_url))
+            .x_frame_options(XFrameOptions::Deny)
+    }
+
+    /// Headers for a GraphQL API endpoint.
+    ///
+    /// This sets `Cross-Origin-Resource-Policy: same-origin`, `Referrer-Policy: no-referrer`,
+    /// `X-Content-Type-Options: nosniff`, `X-Frame-Options: DENY` and the default
+    /// `Strict-Transport-Security`. There is no CSP, as GraphQL endpoints serve JSON rather than
+    /// HTML, and a CSP only restricts documents.
+    ///
+    /// These headers don't stop cross-site request forgery. Use `SameSite` cookies, only accept
+    /// `POST` with `Content-Type: application/json`, and configure CORS to allow only the
+    /// origins that should query the API.
+    pub fn for_graphql() -> Self {
+        Self::new_empty()
+            .cross_origin_resource_policy(CrossOriginResourcePolicy::SameOrigin)
+            .referrer_policy(ReferrerPolicy::NoReferrer)
+            .x_content_type_options(XContentTypeOptions)
+            .x_frame_options(XFrameOptions::Deny)
+            .strict_transport_security(StrictTransportSecurity::DEFAULT)
+    }
+
+    /// [`Self::for_graphql`], with a CSP that lets the GraphQL Playground UI load its scripts,
+    /// styles and fonts from `cdn.jsdelivr.net` and Google Fonts. The playground uses inline
+    /// scripts, so this allows `'unsafe-inline'`. Only serve it in development.
+    pub fn for_graphql_playground() -> Self {
+        let cdn = || CspSource::host_static("https://cdn.jsdelivr.net");
+        let csp = ContentSecurityPolicy::new_empty()
+            .default_src([CspSource::SelfOrigin])
+            .script_src([CspSource::SelfOrigin, CspSource::UnsafeInline, cdn()])
+            .style_src([
+                CspSource::SelfOrigin,
+                CspSource::UnsafeInline,
+                cdn(),
+                CspSource::host_static("https://fonts.googleapis.com"),
+            ])
+            .font_src([
+                CspSource::SelfOrigin,
+                CspSource::host_static("https://fonts.gstatic.com"),
+            ])
+            .img_src([CspSource::SelfOrigin, CspSchemeSource::Data.into(), cdn()])
+            .connect_src([CspSource::SelfOrigin])
+            .object_src([CspSource::None])
+            .base_uri([CspSource::SelfOrigin]);
+        Self::for_graphql().content_security_policy(csp)
+    }
+
+    /// The default headers, but with [`ContentSecurityPolicy::strict_default`] only in
+    /// `Content-Security-Policy-Report-Only`, for rolling out a CSP without breaking anything.
+    /// Add [`Self::with_reporting`] to collect the violations.
+    ///
+    /// Once the reports are clean, promote the policy by moving it to the enforced slot:
+    ///
+    /// ```rust
+    /// # use tower_sombrero::{headers::ContentSecurityPolicy, Sombrero};
+    /// let enforced = Sombrero::report_only_everything()
+    ///     .remove_content_security_policy_report_only()
+    ///     .content_security_policy(ContentSecurityPolicy::strict_default());
+    /// ```
+    pub fn report_only_everything() -> Self {
+        Self::default()
+            .remove_content_security_policy()
+            .content_security_policy_report_only(ContentSecurityPolicy::strict_default())
+    }
+
+    /// [`Self::default`], adjusted to match how the application's cookies are sent.
+    ///
+    /// - [`SameSite::Strict`]: cookies never leave the site, so nothing cross-origin needs to
+    ///   work either. Adds `Cross-Origin-Embedder-Policy: require-corp`, on top of the default
+    ///   `same-origin` resource and opener policies.
+    /// - [`SameSite::Lax`]: cookies follow top-level navigations from other sites. The defaults
+    ///   fit, but `Cross-Origin-Resource-Policy` is `same-site`, so sibling subdomains sharing
+    ///   the session can load resources.
+    /// - [`SameSite::None`]: cookies are meant to be sent cross-origin, which is pointless if
+    ///   the responses can't be used there. `Cross-Origin-Resource-Policy` is `cross-origin` and
+    ///   `Cross-Origin-Embedder-Policy` is `unsafe-none`. Pair this with a CORS layer listing the
+    ///   allowed origins.
+    pub fn recommended_for_cookies(samesite: SameSite) -> Self {
+        let sombrero = Self::default();
+        match samesite {
+            SameSite::Strict => sombrero
+                .cross_origin_embedder_policy(CrossOriginEmbedderPolicy::RequireCorp)
+                .cross_origin_resource_policy(CrossOriginResourcePolicy::SameOrigin),
+            SameSite::Lax => {
+                sombrero.cross_origin_resource_policy(CrossOriginResourcePolicy::SameSite)
+            }
+            SameSite::None => sombrero
+                .cross_origin_resource_policy(CrossOriginResourcePolicy::CrossOrigin)
+                .cross_origin_embedder_policy(CrossOriginEmbedderPolicy::UnsafeNone),
+        }
+    }
+
+    /// [`Self::default`], with a `Cross-Origin-Resource-Policy` that agrees with a CORS layer,
+    /// such as `tower_http::cors::CorsLayer`.
+    ///
+    /// CORS decides which origins may *read* a response with `fetch`. CORP decides which origins
+    /// may *embed* it without CORS, in `<img>`, `<script>` and the like, and which documents
+    /// with `Cross-Origin-Embedder-Policy: require-corp` may load it. A response sending
+    /// `Access-Control-Allow-Origin: *` next to `Cross-Origin-Resource-Policy: same-origin`
+    /// can be fetched from anywhere, but not embedded in any other site. That is rarely intended.
+    ///
+    /// - `cors_allows_any_origin`: the CORS layer answers `Access-Control-Allow-Origin: *`,
+    ///   for public APIs and assets. CORP is `cross-origin` to match.
+    /// - Otherwise, the CORS layer lists the origins it allows, or there is none. CORP stays
+    ///   `same-origin`, so other origins can still read responses through CORS but not embed
+    ///   them.
+    ///
+    /// CORP can't list origins, so for other setups, see
+    /// [`Self::cross_origin_resource_policy_when`]. [`Self::check_cors_compatibility`]
+    /// checks the headers of an actual CORS response.
+    pub fn cors_compatible(cors_allows_any_origin: bool) -> Self {
+        let corp = if cors_allows_any_origin {
+            CrossOriginResourcePolicy::CrossOrigin
+        } else {
+            CrossOriginResourcePolicy::SameOrigin
+        };
+        Self::default().cross_origin_resource_policy(corp)
+    }
+
+    /// Look for headers in `cors_headers`, as sent by a CORS layer, that contradict this
+    /// configuration. See [`Self::cors_compatible`].
+    pub fn check_cors_compatibility(&self, cors_headers: &HeaderMap) -> Vec<SombreroConflict> {
+        let mut conflicts = Vec::new();
+        let any_origin = cors_headers
+            .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_some_and(|origin| origin == "*");
+        let corp_restricts = self
+            .cross_origin_resource_policy
+            .is_some_and(|corp| corp != CrossOriginResourcePolicy::CrossOrigin);
+        if any_origin && corp_restricts {
+            conflicts.push(SombreroConflict::CorpContradictsCorsAnyOrigin);
+        }
+        conflicts
+    }
+
+    /// The same headers [Helmet](https://helmetjs.github.io) sends by default, for one-line
+    /// migrations from Express. This is [`Self::default`] plus `X-DNS-Prefetch-Control: off`.
+    ///
+    /// | Header                              | Value                                        |
+    /// |-------------------------------------|----------------------------------------------|
+    /// | `Content-Security-Policy`           | [`ContentSecurityPolicy::strict_default`]    |
+    /// | `Cross-Origin-Opener-Policy`        | `same-origin`                                |
+    /// | `Cross-Origin-Resource-Policy`      | `same-origin`                                |
+    /// | `Origin-Agent-Cluster`              | `?1`                                         |
+    /// | `Referrer-Policy`                   | `no-referrer`                                |
+    /// | `Strict-Transport-Security`         | `max-age=15552000;includeSubDomains`         |
+    /// | `X-Content-Type-Options`            | `nosniff`                                    |
+    /// | `X-DNS-Prefetch-Control`            | `off`                                        |
+    /// | `X-Download-Options`                | `noopen`                                     |
+    /// | `X-Frame-Options`                   | `SAMEORIGIN`                                 |
+    /// | `X-Permitted-Cross-Domain-Policies` | `none`                                       |
+    /// | `X-XSS-Protection`                  | `0`                                          |
+    pub fn helmet_compatible() -> Self {
+        Self::default().x_dns_prefetch_control(XDnsPrefetchControl::Off)
+    }
+
+    /// Shorthand for an empty [`Self::owasp_conformance_check`].
+    pub fn is_owasp_compliant(&self) -> bool {
+        self.owasp_conformance_check().is_empty()
+    }
+
+    /// Look for headers in this configuration that work against each other.
+    pub fn detect_conflicts(&self) -> Vec<SombreroConflict> {
+        let mut conflicts = Vec::new();
+        let csp_requires_https = self
+            .content_security_policy
+            .as_deref()
+            .is_some_and(ContentSecurityPolicy::requires_https);
+        if csp_requires_https && self.strict_transport_security.is_none() {
+            conflicts.push(SombreroConflict::CspRequiresHttpsWithoutHsts);
+        }
+        conflicts
+    }
+
+    /// The [`CspSecurityLevel`] of the enforced content security policy, if there is one.
+    pub fn csp_security_level(&self) -> Option<CspSecurityLevel> {
+        self.content_security_policy
+            .as_deref()
+            .map(ContentSecurityPolicy::minimum_security_level)
+    }
+
+    /// Every header this config applies with `nonce`, with its value and why it is sent, for
+    /// audits and startup logs. Headers whose value changes the reasoning, like
+    /// `X-DNS-Prefetch-Control` and `X-XSS-Protection`, are explained for the value they have.
+    pub fn describe(&self, nonce: &str) -> Vec<(HeaderName, HeaderValue, &'static str)> {
+        let mut m = HeaderMap::new();
+        self.insert_headers(&mut m, nonce);
+        m.iter()
+            .map(|(name, value)| {
+                let rationale = if *name == self.csp_header_name {
+                    header_rationale(&CONTENT_SECURITY_POLICY, value)
+                } else if *name == CACHE_CONTROL && !self.uses_nonce() {
+                    "Keeps responses out of caches, so streamed responses like server-sent \
+                     events aren't stored or replayed."
+                } else {
+                    header_rationale(name, value)
+                };
+                (name.clone(), value.clone(), rationale)
+            })
+            .collect()
+    }
+
+    /// Recommended headers this configuration does not set. Advisory only, meant for a warning
+    /// at startup. `frame-ancestors` in the enforced CSP counts as `X-Frame-Options`.
+    pub fn missing_recommended(&self) -> Vec<&'static str> {
+        let frame_ancestors = self
+            .content_security_policy
+            .as_ref()
+            .is_some_and(|csp| !csp.frame_ancestors.is_empty());
+        [
+            (
+                "Content-Security-Policy",
+                self.content_security_policy.is_some(),
+            ),
+            (
+                "Strict-Transport-Security",
+                self.strict_transport_security.is_some(),
+            ),
+            (
+                "X-Content-Type-Options",
+                self.x_content_type_options.is_some(),
+            ),
+            (
+                "X-Frame-Options",
+                self.x_frame_options.is_some() || frame_ancestors,
+            ),
+            ("Referrer-Policy", self.referrer_policy.is_some()),
+            (
+                "Cross-Origin-Opener-Policy",
+                self.cross_origin_opener_policy.is_some(),
+            ),
+            (
+                "Cross-Origin-Resource-Policy",
+                self.cross_origin_resource_policy.is_some(),
+            ),
+            ("Permissions-Policy", self.permissions_policy.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, set)| !set)
+        .map(|(name, _)| name)
+        .collect()
+    }
 }
 
 impl Default for Sombrero {
@@ -166,24 +1142,64 @@ impl Default for Sombrero {
             x_frame_options: Some(XFrameOptions::Sameorigin),
             x_permitted_cross_domain_policies: Some(XPermittedCrossDomainPolicies::None),
             x_xss_protection: Some(XXssProtection::False),
+            expect_ct: None,
+            reporting_endpoints: None,
+            permissions_policy: None,
+            skip_csp_on_rate_limit: false,
+            expose_csp_header: false,
+            no_cache_with_nonce: false,
+            no_store: false,
+            respect_existing_csp: false,
+            strip_downstream_csp: false,
+            missing_only: false,
+            nonce_generator: None,
+            strip_for_http10_clients: false,
+            csp_header_name: CONTENT_SECURITY_POLICY,
+            cross_origin_resource_policy_overrides: Vec::new(),
         }
     }
 }
 
+/// [`Sombrero::default`], enforcing `csp` instead.
+impl From<ContentSecurityPolicy> for Sombrero {
+    fn from(csp: ContentSecurityPolicy) -> Self {
+        Self::default().content_security_policy(csp)
+    }
+}
+
+/// [`Sombrero::default`], enforcing `csp` instead, or no CSP at all for `None`.
+impl From<Option<ContentSecurityPolicy>> for Sombrero {
+    fn from(csp: Option<ContentSecurityPolicy>) -> Self {
+        csp.map_or_else(
+            || Self::default().remove_content_security_policy(),
+            Self::from,
+        )
+    }
+}
+
+/// [`Sombrero::default`], enforcing the first policy and reporting on the second.
+impl From<(ContentSecurityPolicy, ContentSecurityPolicy)> for Sombrero {
+    fn from((enforced, report_only): (ContentSecurityPolicy, ContentSecurityPolicy)) -> Self {
+        Self::from(enforced).content_security_policy_report_only(report_only)
+    }
+}
+
 impl<S> Layer<S> for Sombrero {
     type Service = SombreroService<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
         SombreroService {
-            sombrero: self.clone(),
+            prepared: PreparedSombrero::new(self.clone()),
             inner,
         }
     }
 }
 
+/// Cloning this only bumps a reference count. The headers that don't depend on the request are
+/// serialized once, when the layer is applied, so each request only serializes the CSP.
 #[derive(Debug, Clone)]
 pub struct SombreroService<S> {
-    sombrero: Sombrero,
+    prepared: Arc<PreparedSombrero>,
     inner: S,
 }
 
@@ -202,28 +1218,123 @@ where
         self.inner.poll_ready(cx)
     }
 
-    fn call(&mut self, mut request: Request<Body>) -> Self::Future {
-        let nonce = random_string(32);
-        let csp = self
-            .sombrero
-            .content_security_policy
-            .as_ref()
-            .map(|csp| csp.value(&nonce).expect(BAD_CSP_MESSAGE));
-        let csp_ro = self
-            .sombrero
-            .content_security_policy_report_only
-            .as_ref()
-            .map(|csp| csp.value(&nonce).expect(BAD_CSP_MESSAGE));
-        request.extensions_mut().insert(CspNonce(nonce));
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        self.prepared.call_service(&mut self.inner, request)
+    }
+}
+
+impl Sombrero {
+    fn uses_nonce(&self) -> bool {
+        [
+            &self.content_security_policy,
+            &self.content_security_policy_report_only,
+        ]
+        .into_iter()
+        .flatten()
+        .any(|csp| csp.uses_nonce())
+    }
+}
+
+/// A [`Sombrero`] with everything that doesn't change between requests worked out ahead of time.
+pub(crate) struct PreparedSombrero {
+    sombrero: Sombrero,
+    static_headers: HeaderMap,
+    uses_nonce: bool,
+    /// How many headers [`Self::insert_headers`] adds at most, reserved up front.
+    header_count: usize,
+}
+
+/// Shows the prepared headers, without the config's [`Debug`] recomputing them.
+impl std::fmt::Debug for PreparedSombrero {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PreparedSombrero")
+            .field("static_headers", &self.static_headers)
+            .field("uses_nonce", &self.uses_nonce)
+            .field("header_count", &self.header_count)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PreparedSombrero {
+    pub(crate) fn new(sombrero: Sombrero) -> Arc<Self> {
+        let mut static_headers = HeaderMap::new();
+        sombrero.insert_static_headers(&mut static_headers);
+        let enforced = sombrero.content_security_policy.is_some();
+        let header_count = static_headers.len()
+            + usize::from(enforced)
+            + usize::from(sombrero.content_security_policy_report_only.is_some())
+            + usize::from(enforced && sombrero.expose_csp_header);
+        let estimated = estimated_header_byte_length(&sombrero, &static_headers);
+        if estimated > HEADER_BUDGET_WARNING_BYTES {
+            tracing::warn!(
+                estimated,
+                budget = HEADER_BUDGET_WARNING_BYTES,
+                "configured headers alone exceed the size limit of common CDNs"
+            );
+        }
+        Arc::new(Self {
+            uses_nonce: sombrero.uses_nonce(),
+            sombrero,
+            static_headers,
+            header_count,
+        })
+    }
+
+    /// The body of [`SombreroService::call`], shared with the other services applying a `Sombrero`.
+    pub(crate) fn call_service<S, Body>(
+        self: &Arc<Self>,
+        inner: &mut S,
+        mut request: Request<Body>,
+    ) -> BoxFuture<'static, Result<Response<Body>, S::Error>>
+    where
+        S: Service<Request<Body>, Response = Response<Body>>,
+        S::Future: Send + 'static,
+        S::Error: 'static,
+        Body: Send + 'static,
+    {
+        // Generated on first use, so policies without `CspSource::Nonce` don't pay for one
+        // unless the handler asks for it.
+        let nonce = CspNonce::lazy(
+            request.extensions().get::<NonceSeed>().copied(),
+            self.sombrero.nonce_generator.clone(),
+        );
+        request.extensions_mut().insert(nonce.clone());
+        let hashes = CspHashes::default();
+        request.extensions_mut().insert(hashes.clone());
+        let modifier = SombreroModifier::new(Arc::clone(self), nonce.clone());
+        request.extensions_mut().insert(modifier.clone());
+        let uri = request.uri().clone();
+        let version = request.version();
 
-        let future = self.inner.call(request);
+        let future = inner.call(request);
         Box::pin(sombrero_svc_middleware(
-            self.sombrero.clone(),
-            csp,
-            csp_ro,
+            Arc::clone(self),
+            nonce,
+            hashes,
+            modifier,
+            uri,
+            version,
             future,
         ))
     }
+
+    fn insert_headers(
+        &self,
+        m: &mut HeaderMap,
+        nonce: &str,
+        hashes: &[CspSource],
+    ) -> Result<(), InvalidHeaderValue> {
+        m.reserve(self.header_count);
+        let result = self.sombrero.insert_csp_headers(m, nonce, hashes);
+        for (name, value) in &self.static_headers {
+            if is_list_header(name) {
+                m.append(name, value.clone());
+            } else {
+                m.insert(name, value.clone());
+            }
+        }
+        result
+    }
 }
 
 fn add_opt_header(map: &mut HeaderMap, header: Option<impl Header>) {
@@ -232,6 +1343,19 @@ fn add_opt_header(map: &mut HeaderMap, header: Option<impl Header>) {
     }
 }
 
+/// Like [`add_opt_header`], but keeps values set by downstream layers. Only for
+/// headers where [`is_list_header`] holds.
+fn add_opt_header_append(map: &mut HeaderMap, header: Option<impl Header>) {
+    if let Some(header) = header {
+        map.append(header.name(), header.value());
+    }
+}
+
+/// Headers that may appear more than once and are combined by the recipient.
+fn is_list_header(name: &HeaderName) -> bool {
+    name == VARY || name == ACCESS_CONTROL_EXPOSE_HEADERS || name == "reporting-endpoints"
+}
+
 fn add_opt_header_raw(
     map: &mut HeaderMap,
     header_name: HeaderName,
@@ -242,38 +1366,224 @@ fn add_opt_header_raw(
     }
 }
 
+/// Why a header is sent with `value`, for [`Sombrero::describe`].
+fn header_rationale(name: &HeaderName, value: &HeaderValue) -> &'static str {
+    match name.as_str() {
+        "content-security-policy" => {
+            "Limits where scripts, styles and other resources can come from, the main defense \
+             against cross-site scripting."
+        }
+        "content-security-policy-report-only" => {
+            "Reports what the policy would block, without blocking anything."
+        }
+        "cross-origin-embedder-policy" => {
+            "Only loads cross-origin resources that opt in, which browsers require before \
+             enabling cross-origin isolation."
+        }
+        "cross-origin-opener-policy" => {
+            "Separates the page from cross-origin windows it opens or is opened by, so they \
+             can't reach into it."
+        }
+        "cross-origin-resource-policy" => {
+            "Decides which sites can embed responses with `<img>`, `<script>` and the like."
+        }
+        "origin-agent-cluster" => {
+            "Asks the browser to isolate the origin, rather than the whole site, in its own \
+             process where possible."
+        }
+        "referrer-policy" => {
+            "Controls how much of the URL is sent to other sites in `Referer`, where paths and \
+             tokens could leak."
+        }
+        "strict-transport-security" => {
+            "Makes browsers use HTTPS until `max-age` runs out, so connections can't be \
+             downgraded to plain HTTP."
+        }
+        "x-content-type-options" => {
+            "Stops browsers from guessing content types, which could turn an upload into a \
+             script."
+        }
+        "x-dns-prefetch-control" if value == "on" => {
+            "Lets browsers look up the domains of links before they are followed, which makes \
+             navigating faster but tells DNS resolvers what the page links to."
+        }
+        "x-dns-prefetch-control" => {
+            "Stops browsers from looking up the domains of links before they are followed, so \
+             DNS resolvers don't learn what the page links to. This makes following links to \
+             other sites a little slower, which is why the default config doesn't send it."
+        }
+        "x-download-options" => {
+            "Keeps old versions of Internet Explorer from opening downloads in the context of the \
+             site."
+        }
+        "x-frame-options" => {
+            "Keeps other sites from framing pages, against clickjacking. CSP `frame-ancestors` \
+             supersedes it, but old browsers only understand this."
+        }
+        "x-permitted-cross-domain-policies" => {
+            "Keeps Flash and Acrobat from loading cross-domain policy files from the site."
+        }
+        "x-xss-protection" if value == "0" => {
+            "Turns off the XSS filter of old browsers. The filter is buggy: it can be abused to \
+             disable scripts selectively and to leak data across origins. Modern browsers \
+             removed it, and the CSP protects against cross-site scripting instead."
+        }
+        "x-xss-protection" => {
+            "Turns on the XSS filter of old browsers, which is buggy and can be abused to leak \
+             data across origins. `0` is safer."
+        }
+        "expect-ct" => "Deprecated. Browsers enforce Certificate Transparency without it.",
+        "reporting-endpoints" => "Names the endpoints that reports, like CSP violations, go to.",
+        "permissions-policy" => {
+            "Turns off browser features, like the camera or geolocation, that pages don't use."
+        }
+        "cache-control" => {
+            "Keeps responses out of caches, so a page and its nonce are never served twice."
+        }
+        "access-control-expose-headers" => {
+            "Lets scripts on other origins allowed by CORS read the CSP header."
+        }
+        _ => "",
+    }
+}
+
+/// Copy every header in `src` whose name is not in `dst` yet.
+fn insert_missing(dst: &mut HeaderMap, src: &HeaderMap) {
+    for name in src.keys() {
+        if !dst.contains_key(name) {
+            for value in src.get_all(name) {
+                dst.append(name, value.clone());
+            }
+        }
+    }
+}
+
+fn is_rate_limited<B>(response: &Response<B>) -> bool {
+    matches!(
+        response.status(),
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    ) && response.headers().contains_key(RETRY_AFTER)
+}
+
 async fn sombrero_svc_middleware<F, B, E>(
-    h: Sombrero,
-    content_security_policy: Option<HeaderValue>,
-    content_security_policy_report_only: Option<HeaderValue>,
+    prepared: Arc<PreparedSombrero>,
+    nonce: CspNonce,
+    hashes: CspHashes,
+    modifier: SombreroModifier,
+    uri: Uri,
+    version: Version,
     response_fut: F,
 ) -> Result<Response<B>, E>
 where
     F: Future<Output = Result<Response<B>, E>> + Send,
 {
     let mut response = response_fut.await?;
-    let m = response.headers_mut();
-    add_opt_header_raw(m, CONTENT_SECURITY_POLICY, content_security_policy);
-    add_opt_header_raw(
-        m,
-        CONTENT_SECURITY_POLICY_REPORT_ONLY,
-        content_security_policy_report_only,
-    );
-    add_opt_header(m, h.cross_origin_embedder_policy);
-    add_opt_header(m, h.cross_origin_opener_policy);
-    add_opt_header(m, h.cross_origin_resource_policy);
-    add_opt_header(m, h.origin_agent_cluster);
-    add_opt_header(m, h.referrer_policy);
-    add_opt_header(m, h.strict_transport_security);
-    add_opt_header(m, h.x_content_type_options);
-    add_opt_header(m, h.x_dns_prefetch_control);
-    add_opt_header(m, h.x_download_options);
-    add_opt_header(m, h.x_frame_options);
-    add_opt_header(m, h.x_permitted_cross_domain_policies);
-    add_opt_header(m, h.x_xss_protection);
+    let modified = modifier.take();
+    let h = modified.as_ref().unwrap_or(&prepared.sombrero);
+    if h.strip_for_http10_clients && version == Version::HTTP_10 {
+        return Ok(response);
+    }
+    let rate_limited = h.skip_csp_on_rate_limit && is_rate_limited(&response);
+    // a CORP the handler set itself wins over the layer's path overrides
+    let corp_override =
+        if h.cross_origin_resource_policy == prepared.sombrero.cross_origin_resource_policy {
+            h.cross_origin_resource_policy_override(uri.path())
+        } else {
+            None
+        };
+    if h.strip_downstream_csp {
+        // also done when inserting, but missing-only mode inserts elsewhere first
+        h.remove_enforced_csp(response.headers_mut());
+    }
+    // in missing-only mode, headers are collected here and only the missing ones copied over
+    let mut missing = HeaderMap::new();
+    let m = if h.missing_only {
+        &mut missing
+    } else {
+        response.headers_mut()
+    };
+    let mut result = Ok(());
+    if rate_limited {
+        add_opt_header(m, h.strict_transport_security);
+        add_opt_header(m, h.x_content_type_options);
+    } else {
+        result = match &modified {
+            Some(modified) => {
+                let nonce = if modified.uses_nonce() {
+                    nonce.value()
+                } else {
+                    ""
+                };
+                modified.insert_headers_with_hashes(m, nonce, &hashes.take())
+            }
+            None => {
+                let nonce = if prepared.uses_nonce {
+                    nonce.value()
+                } else {
+                    ""
+                };
+                prepared.insert_headers(m, nonce, &hashes.take())
+            }
+        };
+        add_opt_header(m, corp_override);
+    }
+    if h.missing_only {
+        insert_missing(response.headers_mut(), &missing);
+    }
+    if let Err(error) = result {
+        fail_closed(&mut response, &error);
+    }
+    if rate_limited {
+        return Ok(response);
+    }
+    let total = header_byte_length(response.headers());
+    if total > HEADER_BUDGET_WARNING_BYTES {
+        tracing::warn!(
+            total,
+            budget = HEADER_BUDGET_WARNING_BYTES,
+            "response headers exceed the size limit of common CDNs"
+        );
+    }
     Ok(response)
 }
 
+/// Turn `response` into a 500 after one of its policies failed to serialize, and log `error`.
+/// The body can't be replaced without knowing its type, but the [`FALLBACK_CSP`] sent in place
+/// of the policy keeps browsers from running anything in it.
+pub(crate) fn fail_closed<B>(response: &mut Response<B>, error: &InvalidHeaderValue) {
+    tracing::error!(%error, "{BAD_CSP_MESSAGE}");
+    *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+}
+
+/// The smallest total response header size CDNs commonly reject above.
+const HEADER_BUDGET_WARNING_BYTES: usize = 8192;
+
+/// The byte length of the headers [`PreparedSombrero`] adds, using
+/// [`ContentSecurityPolicy::estimated_len`] for the policies as the nonce isn't known yet.
+fn estimated_header_byte_length(sombrero: &Sombrero, static_headers: &HeaderMap) -> usize {
+    let enforced = sombrero.content_security_policy.as_ref().map_or(0, |csp| {
+        sombrero.csp_header_name.as_str().len() + csp.estimated_len()
+    });
+    let report_only = sombrero
+        .content_security_policy_report_only
+        .as_ref()
+        .map_or(0, |csp| {
+            CONTENT_SECURITY_POLICY_REPORT_ONLY.as_str().len() + csp.estimated_len()
+        });
+    let exposed = if sombrero.sends_exposed_csp() {
+        ACCESS_CONTROL_EXPOSE_HEADERS.as_str().len() + sombrero.csp_header_name.as_str().len()
+    } else {
+        0
+    };
+    header_byte_length(static_headers) + enforced + report_only + exposed
+}
+
+fn header_byte_length(map: &HeaderMap) -> usize {
+    map.iter()
+        .map(|(name, value)| name.as_str().len() + value.len())
+        .sum()
+}
+
 pub async fn middleware_add_raw_header<F, B, E>(
     header_name: HeaderName,
     header_value: HeaderValue,
@@ -287,7 +1597,19 @@ where
     Ok(response)
 }
 
+#[cfg(test)]
+thread_local! {
+    /// Counts [`random_string`] calls on this thread, so tests can check that nonces are skipped.
+    pub(crate) static RANDOM_STRINGS_GENERATED: std::cell::Cell<usize> =
+        const { std::cell::Cell::new(0) };
+}
+
+/// The length of the nonces generated when no [`NonceGenerator`] is configured.
+pub(crate) const NONCE_LENGTH: usize = 32;
+
 pub fn random_string(length: usize) -> String {
+    #[cfg(test)]
+    RANDOM_STRINGS_GENERATED.set(RANDOM_STRINGS_GENERATED.get() + 1);
     rand::rng()
         .sample_iter(Alphanumeric)
         .take(length)
@@ -295,6 +1617,40 @@ pub fn random_string(length: usize) -> String {
         .collect()
 }
 
+/// The `SameSite` attribute of an application's cookies, for [`Sombrero::recommended_for_cookies`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+/// Who may embed pages in frames, for [`Sombrero::frame_protection`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FrameProtection {
+    /// `X-Frame-Options: DENY` and `frame-ancestors 'none'`.
+    DenyAll,
+    /// `X-Frame-Options: SAMEORIGIN` and `frame-ancestors 'self'`.
+    SameOrigin,
+    /// `frame-ancestors` with these origins, and no `X-Frame-Options`.
+    ///
+    /// `X-Frame-Options` can't express a list of origins. Sending `DENY` would break embedding
+    /// in the old browsers that only understand it, while modern browsers ignore it whenever
+    /// `frame-ancestors` is present, so it is removed instead.
+    AllowOrigins(Vec<String>),
+}
+
+/// Headers in a [`Sombrero`] that work against each other, from [`Sombrero::detect_conflicts`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SombreroConflict {
+    /// The CSP only works over HTTPS (see [`ContentSecurityPolicy::requires_https`]),
+    /// but no `Strict-Transport-Security` header keeps clients from using plain HTTP.
+    CspRequiresHttpsWithoutHsts,
+    /// CORS allows any origin to read responses, but `Cross-Origin-Resource-Policy` keeps
+    /// other origins from embedding them. Found by [`Sombrero::check_cors_compatibility`].
+    CorpContradictsCorsAnyOrigin,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[cfg(feature = "axum")]
@@ -0,0 +1,63 @@
+//! [`minijinja`] integration.
+//!
+//! [`CspNonce`] can be passed straight into a template context, where it renders as the raw nonce.
+//! Register [`filters::nonce_attr`] as a filter to render the whole attribute:
+//!
+//! ```rust
+//! # use tower_sombrero::{csp::CspNonce, minijinja::filters::nonce_attr};
+//! let mut env = minijinja::Environment::new();
+//! env.add_filter("nonce_attr", nonce_attr);
+//! env.add_template("page.html", "<script {{ nonce|nonce_attr }}></script>")
+//!     .unwrap();
+//! let nonce = CspNonce::new("abc");
+//! let html = env
+//!     .get_template("page.html")
+//!     .unwrap()
+//!     .render(minijinja::context! { nonce => nonce.to_minijinja_value() })
+//!     .unwrap();
+//! assert_eq!(html, r#"<script nonce="abc"></script>"#);
+//! ```
+
+use std::{
+    fmt::{Formatter, Result as FmtResult},
+    sync::Arc,
+};
+
+use ::minijinja::{
+    value::{Object, ObjectRepr},
+    Value,
+};
+
+use crate::csp::CspNonce;
+
+impl Object for CspNonce {
+    fn repr(self: &Arc<Self>) -> ObjectRepr {
+        ObjectRepr::Plain
+    }
+
+    fn render(self: &Arc<Self>, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(self.value())
+    }
+}
+
+impl CspNonce {
+    /// This nonce as a template value, rendering as the raw nonce.
+    pub fn to_minijinja_value(&self) -> Value {
+        Value::from_object(self.clone())
+    }
+}
+
+/// Filters to register with [`Environment::add_filter`](::minijinja::Environment::add_filter).
+pub mod filters {
+    use ::minijinja::Value;
+
+    use crate::csp::CspNonce;
+
+    /// Filter turning a [`CspNonce`] (or a plain string) into `nonce="value"`.
+    pub fn nonce_attr(value: &Value) -> Value {
+        let nonce = value
+            .downcast_object_ref::<CspNonce>()
+            .map_or_else(|| value.to_string(), ToString::to_string);
+        Value::from_safe_string(format!("nonce=\"{nonce}\""))
+    }
+}
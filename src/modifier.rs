@@ -0,0 +1,67 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::{csp::CspNonce, PreparedSombrero, Sombrero};
+
+/// Changes the [`Sombrero`] config for the current response only.
+///
+/// Sombrero puts one of these in the request extensions, and with the `axum` feature it is also
+/// an extractor. Handlers call [`Self::modify`] with the usual builder methods, and the modified
+/// config is applied when the response comes back instead of the layer's own:
+///
+/// ```rust
+/// # use tower_sombrero::SombreroModifier;
+/// async fn pdf(modifier: SombreroModifier) -> &'static str {
+///     // let PDF viewers embed this response
+///     modifier.modify(|sombrero| sombrero.remove_x_frame_options());
+///     "%PDF-1.7"
+/// }
+/// ```
+///
+/// A modification adding [`CspSource::Nonce`](crate::headers::CspSource::Nonce) uses the
+/// request's nonce, which [`Self::nonce`] gives the handler for its markup.
+#[derive(Debug, Clone)]
+pub struct SombreroModifier(Arc<ModifierState>);
+
+#[derive(Debug)]
+struct ModifierState {
+    base: Arc<PreparedSombrero>,
+    nonce: CspNonce,
+    modified: Mutex<Option<Sombrero>>,
+}
+
+impl SombreroModifier {
+    pub(crate) fn new(base: Arc<PreparedSombrero>, nonce: CspNonce) -> Self {
+        Self(Arc::new(ModifierState {
+            base,
+            nonce,
+            modified: Mutex::new(None),
+        }))
+    }
+
+    /// Change the config for this response. Repeated calls build on each other.
+    pub fn modify(&self, f: impl FnOnce(Sombrero) -> Sombrero) {
+        let mut modified = self
+            .0
+            .modified
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let current = modified
+            .take()
+            .unwrap_or_else(|| self.0.base.sombrero.clone());
+        *modified = Some(f(current));
+    }
+
+    /// The nonce of this request, the same as its [`CspNonce`] extension.
+    pub fn nonce(&self) -> CspNonce {
+        self.0.nonce.clone()
+    }
+
+    /// The modified config, if [`Self::modify`] was called.
+    pub(crate) fn take(&self) -> Option<Sombrero> {
+        self.0
+            .modified
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+}
@@ -0,0 +1,208 @@
+//! An estimate of the [Mozilla HTTP Observatory] grade a [`Sombrero`] config would earn.
+//!
+//! Only the header-based tests are scored. Cookies, redirects, subresource integrity and CORS
+//! are outside of what `Sombrero` controls, and are assumed to pass.
+//!
+//! [Mozilla HTTP Observatory]: https://developer.mozilla.org/en-US/observatory/docs/tests_and_scoring
+
+use crate::{
+    headers::{CspSchemeSource, CspSource, ReferrerPolicy},
+    Sombrero,
+};
+
+/// The letter grade, ordered so that better grades compare greater.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Grade {
+    F,
+    DMinus,
+    D,
+    DPlus,
+    CMinus,
+    C,
+    CPlus,
+    BMinus,
+    B,
+    BPlus,
+    AMinus,
+    A,
+    APlus,
+}
+
+impl Grade {
+    const fn from_points(points: i32) -> Self {
+        match points {
+            100.. => Self::APlus,
+            90..=99 => Self::A,
+            85..=89 => Self::AMinus,
+            80..=84 => Self::BPlus,
+            70..=79 => Self::B,
+            65..=69 => Self::BMinus,
+            60..=64 => Self::CPlus,
+            50..=59 => Self::C,
+            45..=49 => Self::CMinus,
+            40..=44 => Self::DPlus,
+            30..=39 => Self::D,
+            25..=29 => Self::DMinus,
+            _ => Self::F,
+        }
+    }
+}
+
+/// A score modifier from one of the Observatory tests. Most are deductions,
+/// but a few are extra credit, which only counts once the score is already 90 or higher.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ObservatoryDeduction {
+    CspNotImplemented,
+    CspUnsafeInline,
+    CspUnsafeEval,
+    CspInsecureScheme,
+    CspNoUnsafeDefaultSrcNone,
+    CspNoUnsafe,
+    HstsMaxAgeTooShort,
+    HstsNotImplemented,
+    ReferrerPolicyPrivate,
+    ReferrerPolicyUnsafe,
+    XContentTypeOptionsNotImplemented,
+    FrameAncestorsImplemented,
+    XFrameOptionsNotImplemented,
+}
+
+impl ObservatoryDeduction {
+    /// How many points this adds to (or, usually, removes from) the score.
+    pub const fn modifier(self) -> i32 {
+        match self {
+            Self::CspNotImplemented => -25,
+            Self::CspUnsafeInline | Self::CspInsecureScheme | Self::HstsNotImplemented => -20,
+            Self::XFrameOptionsNotImplemented => -20,
+            Self::CspUnsafeEval | Self::HstsMaxAgeTooShort => -10,
+            Self::ReferrerPolicyUnsafe | Self::XContentTypeOptionsNotImplemented => -5,
+            Self::CspNoUnsafeDefaultSrcNone => 10,
+            Self::CspNoUnsafe | Self::ReferrerPolicyPrivate | Self::FrameAncestorsImplemented => 5,
+        }
+    }
+
+    const fn is_extra_credit(self) -> bool {
+        self.modifier() > 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ObservatoryScore {
+    pub grade: Grade,
+    pub deductions: Vec<ObservatoryDeduction>,
+    pub points: i32,
+}
+
+/// Six months, the minimum HSTS `max-age` the Observatory accepts.
+const HSTS_MIN_MAX_AGE: u64 = 15_768_000;
+
+impl Sombrero {
+    /// Estimate the Mozilla HTTP Observatory grade for this config. See the [module docs](crate::observatory).
+    pub fn mozilla_observatory_score(&self) -> ObservatoryScore {
+        let mut deductions = Vec::new();
+        self.observatory_csp(&mut deductions);
+
+        match self.strict_transport_security {
+            None => deductions.push(ObservatoryDeduction::HstsNotImplemented),
+            Some(sts) if sts.max_age < HSTS_MIN_MAX_AGE => {
+                deductions.push(ObservatoryDeduction::HstsMaxAgeTooShort);
+            }
+            Some(_) => {}
+        }
+
+        match self.referrer_policy {
+            None => {}
+            Some(
+                ReferrerPolicy::NoReferrer
+                | ReferrerPolicy::SameOrigin
+                | ReferrerPolicy::StrictOrigin
+                | ReferrerPolicy::StrictOriginWhenCrossOrigin,
+            ) => deductions.push(ObservatoryDeduction::ReferrerPolicyPrivate),
+            Some(_) => deductions.push(ObservatoryDeduction::ReferrerPolicyUnsafe),
+        }
+
+        if self.x_content_type_options.is_none() {
+            deductions.push(ObservatoryDeduction::XContentTypeOptionsNotImplemented);
+        }
+
+        let frame_ancestors = self
+            .content_security_policy
+            .as_ref()
+            .is_some_and(|csp| !csp.frame_ancestors.is_empty());
+        if frame_ancestors {
+            deductions.push(ObservatoryDeduction::FrameAncestorsImplemented);
+        } else if self.x_frame_options.is_none() {
+            deductions.push(ObservatoryDeduction::XFrameOptionsNotImplemented);
+        }
+
+        let penalties: i32 = deductions
+            .iter()
+            .filter(|d| !d.is_extra_credit())
+            .map(|d| d.modifier())
+            .sum();
+        let mut points = (100 + penalties).max(0);
+        if points >= 90 {
+            points += deductions
+                .iter()
+                .filter(|d| d.is_extra_credit())
+                .map(|d| d.modifier())
+                .sum::<i32>();
+        }
+        ObservatoryScore {
+            grade: Grade::from_points(points),
+            deductions,
+            points,
+        }
+    }
+
+    fn observatory_csp(&self, deductions: &mut Vec<ObservatoryDeduction>) {
+        let Some(csp) = &self.content_security_policy else {
+            deductions.push(ObservatoryDeduction::CspNotImplemented);
+            return;
+        };
+        let fallback = |sources: &'_ [CspSource]| -> Vec<CspSource> {
+            if sources.is_empty() {
+                csp.default_src.clone()
+            } else {
+                sources.to_vec()
+            }
+        };
+        let script_src = fallback(&csp.script_src);
+        let style_src = fallback(&csp.style_src);
+
+        // browsers ignore 'unsafe-inline' when a nonce or hash is present
+        let script_inline = script_src.contains(&CspSource::UnsafeInline)
+            && !script_src
+                .iter()
+                .any(|s| matches!(s, CspSource::Nonce | CspSource::Hash(..)));
+        let script_eval = script_src.contains(&CspSource::UnsafeEval);
+        let insecure = [&script_src, &fallback(&csp.object_src)]
+            .iter()
+            .flat_map(|sources| sources.iter())
+            .any(|s| match s {
+                CspSource::Scheme(CspSchemeSource::Http) | CspSource::Wildcard => true,
+                CspSource::Host(host) => host.starts_with("http:"),
+                _ => false,
+            });
+
+        if script_inline {
+            deductions.push(ObservatoryDeduction::CspUnsafeInline);
+        }
+        if script_eval {
+            deductions.push(ObservatoryDeduction::CspUnsafeEval);
+        }
+        if insecure {
+            deductions.push(ObservatoryDeduction::CspInsecureScheme);
+        }
+        if script_inline || script_eval || insecure {
+            return;
+        }
+        if style_src.contains(&CspSource::UnsafeInline) {
+            // unsafe-inline in style-src only is neither penalized nor rewarded
+        } else if csp.default_src.as_slice() == [CspSource::None] {
+            deductions.push(ObservatoryDeduction::CspNoUnsafeDefaultSrcNone);
+        } else {
+            deductions.push(ObservatoryDeduction::CspNoUnsafe);
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::fmt::{Debug, Display, Formatter};
+
+use http::{
+    header::{
+        InvalidHeaderValue, ACCESS_CONTROL_EXPOSE_HEADERS, CACHE_CONTROL,
+        CONTENT_SECURITY_POLICY_REPORT_ONLY,
+    },
+    HeaderMap, HeaderName, HeaderValue,
+};
+
+use crate::{headers::Header, Sombrero};
+
+/// Shows the headers a [`Sombrero`] adds, one `name: value` per line, using the nonce
+/// `.1` for the content security policies.
+///
+/// ```rust
+/// # use tower_sombrero::{Sombrero, SombreroPretty};
+/// let pretty = SombreroPretty(&Sombrero::default(), "n0nce").to_string();
+/// assert!(pretty.contains("x-content-type-options: nosniff\n"));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct SombreroPretty<'a>(pub &'a Sombrero, pub &'a str);
+
+impl Display for SombreroPretty<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut headers = HeaderMap::new();
+        self.0.insert_headers(&mut headers, self.1);
+        for (name, value) in &headers {
+            writeln!(f, "{name}: {}", String::from_utf8_lossy(value.as_bytes()))?;
+        }
+        Ok(())
+    }
+}
+
+/// The nonce computed header values are shown with in [`Debug`] output.
+const DEBUG_NONCE: &str = "NONCE";
+
+/// The headers of `sombrero`, as [`Sombrero::insert_headers`] would add them. Values that fail
+/// to serialize are kept as errors, where inserting would log or panic.
+#[allow(deprecated)]
+fn debug_headers(
+    sombrero: &Sombrero,
+) -> Vec<(HeaderName, Result<HeaderValue, InvalidHeaderValue>)> {
+    fn infallible(header: impl Header) -> (HeaderName, Result<HeaderValue, InvalidHeaderValue>) {
+        (header.name(), Ok(header.value()))
+    }
+
+    let mut headers = Vec::new();
+    if sombrero.sends_exposed_csp() {
+        let value = HeaderValue::from(sombrero.csp_header_name.clone());
+        headers.push((ACCESS_CONTROL_EXPOSE_HEADERS, Ok(value)));
+    }
+    if let Some(csp) = &sombrero.content_security_policy {
+        headers.push((sombrero.csp_header_name.clone(), csp.value(DEBUG_NONCE)));
+    }
+    if let Some(csp) = &sombrero.content_security_policy_report_only {
+        headers.push((CONTENT_SECURITY_POLICY_REPORT_ONLY, csp.value(DEBUG_NONCE)));
+    }
+    let infallible_headers = [
+        sombrero.cross_origin_embedder_policy.map(infallible),
+        sombrero.cross_origin_opener_policy.map(infallible),
+        sombrero.cross_origin_resource_policy.map(infallible),
+        sombrero.origin_agent_cluster.map(infallible),
+        sombrero.referrer_policy.map(infallible),
+        sombrero.strict_transport_security.map(infallible),
+        sombrero.x_content_type_options.map(infallible),
+        sombrero.x_dns_prefetch_control.map(infallible),
+        sombrero.x_download_options.map(infallible),
+        sombrero.x_frame_options.map(infallible),
+        sombrero.x_permitted_cross_domain_policies.map(infallible),
+        sombrero.x_xss_protection.map(infallible),
+    ];
+    headers.extend(infallible_headers.into_iter().flatten());
+    if let Some(expect_ct) = &sombrero.expect_ct {
+        headers.push((expect_ct.name(), expect_ct.try_value()));
+    }
+    if let Some(endpoints) = &sombrero.reporting_endpoints {
+        headers.push((endpoints.name(), endpoints.try_value()));
+    }
+    if let Some(policy) = &sombrero.permissions_policy {
+        headers.push((policy.name(), policy.try_value()));
+    }
+    if sombrero.sends_no_store() {
+        headers.push((CACHE_CONTROL, Ok(HeaderValue::from_static("no-store"))));
+    }
+    headers
+}
+
+/// A header value for [`Debug`] output, with errors shown as `<invalid: error>`.
+struct DebugValue<'a>(&'a Result<HeaderValue, InvalidHeaderValue>);
+
+impl Debug for DebugValue<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Ok(value) => Debug::fmt(value, f),
+            Err(error) => write!(f, "<invalid: {error}>"),
+        }
+    }
+}
+
+/// The computed headers, a field of [`Sombrero`]'s [`Debug`] output.
+struct DebugHeaders<'a>(&'a Sombrero);
+
+impl Debug for DebugHeaders<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let headers = debug_headers(self.0);
+        f.debug_map()
+            .entries(
+                headers
+                    .iter()
+                    .map(|(name, value)| (name, DebugValue(value))),
+            )
+            .finish()
+    }
+}
+
+/// Lists the fields, then the `headers` they compute to, with the nonce `NONCE`. Computing
+/// them never logs or panics: values that can't be serialized are shown as `<invalid: ..>`.
+impl Debug for Sombrero {
+    #[allow(deprecated)]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // destructured so that new fields can't be forgotten here
+        let Self {
+            content_security_policy,
+            content_security_policy_report_only,
+            cross_origin_embedder_policy,
+            cross_origin_opener_policy,
+            cross_origin_resource_policy,
+            origin_agent_cluster,
+            referrer_policy,
+            strict_transport_security,
+            x_content_type_options,
+            x_dns_prefetch_control,
+            x_download_options,
+            x_frame_options,
+            x_permitted_cross_domain_policies,
+            x_xss_protection,
+            expect_ct,
+            reporting_endpoints,
+            permissions_policy,
+            skip_csp_on_rate_limit,
+            expose_csp_header,
+            no_cache_with_nonce,
+            no_store,
+            respect_existing_csp,
+            strip_downstream_csp,
+            missing_only,
+            strip_for_http10_clients,
+            nonce_generator,
+            csp_header_name,
+            cross_origin_resource_policy_overrides,
+        } = self;
+        f.debug_struct("Sombrero")
+            .field("content_security_policy", content_security_policy)
+            .field(
+                "content_security_policy_report_only",
+                content_security_policy_report_only,
+            )
+            .field("cross_origin_embedder_policy", cross_origin_embedder_policy)
+            .field("cross_origin_opener_policy", cross_origin_opener_policy)
+            .field("cross_origin_resource_policy", cross_origin_resource_policy)
+            .field("origin_agent_cluster", origin_agent_cluster)
+            .field("referrer_policy", referrer_policy)
+            .field("strict_transport_security", strict_transport_security)
+            .field("x_content_type_options", x_content_type_options)
+            .field("x_dns_prefetch_control", x_dns_prefetch_control)
+            .field("x_download_options", x_download_options)
+            .field("x_frame_options", x_frame_options)
+            .field(
+                "x_permitted_cross_domain_policies",
+                x_permitted_cross_domain_policies,
+            )
+            .field("x_xss_protection", x_xss_protection)
+            .field("expect_ct", expect_ct)
+            .field("reporting_endpoints", reporting_endpoints)
+            .field("permissions_policy", permissions_policy)
+            .field("skip_csp_on_rate_limit", skip_csp_on_rate_limit)
+            .field("expose_csp_header", expose_csp_header)
+            .field("no_cache_with_nonce", no_cache_with_nonce)
+            .field("no_store", no_store)
+            .field("respect_existing_csp", respect_existing_csp)
+            .field("strip_downstream_csp", strip_downstream_csp)
+            .field("missing_only", missing_only)
+            .field("strip_for_http10_clients", strip_for_http10_clients)
+            .field("nonce_generator", nonce_generator)
+            .field("csp_header_name", csp_header_name)
+            .field(
+                "cross_origin_resource_policy_overrides",
+                cross_origin_resource_policy_overrides,
+            )
+            .field("headers", &DebugHeaders(self))
+            .finish()
+    }
+}
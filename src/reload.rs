@@ -0,0 +1,111 @@
+use std::{
+    sync::{Arc, PoisonError, RwLock},
+    task::{Context, Poll},
+};
+
+use futures_util::future::BoxFuture;
+use http::{Request, Response};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{PreparedSombrero, Sombrero};
+
+/// A [`Sombrero`] layer whose config can be replaced while the server is running, through a
+/// [`ReloadableSombreroHandle`].
+///
+/// Each request uses the config current when it arrived, even if it is replaced before the
+/// response comes back. Configs are prepared once, on [`ReloadableSombreroHandle::update`],
+/// so requests only clone an [`Arc`] under the lock.
+///
+/// ```rust
+/// # use tower_sombrero::{ReloadableSombrero, Sombrero};
+/// let (layer, handle) = ReloadableSombrero::new(Sombrero::default()).split();
+/// // give `layer` to the router, keep `handle` around
+/// handle.update(Sombrero::default().remove_content_security_policy());
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReloadableSombrero {
+    current: Arc<RwLock<Arc<PreparedSombrero>>>,
+}
+
+impl ReloadableSombrero {
+    pub fn new(sombrero: Sombrero) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(PreparedSombrero::new(sombrero))),
+        }
+    }
+
+    /// A handle replacing the config of this layer, and of every service it made.
+    pub fn handle(&self) -> ReloadableSombreroHandle {
+        ReloadableSombreroHandle {
+            current: Arc::clone(&self.current),
+        }
+    }
+
+    /// The layer, and a [`Self::handle`] for it.
+    pub fn split(self) -> (Self, ReloadableSombreroHandle) {
+        let handle = self.handle();
+        (self, handle)
+    }
+}
+
+impl<S> Layer<S> for ReloadableSombrero {
+    type Service = ReloadableSombreroService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ReloadableSombreroService {
+            current: Arc::clone(&self.current),
+            inner,
+        }
+    }
+}
+
+/// Replaces the config of a [`ReloadableSombrero`].
+#[derive(Debug, Clone)]
+pub struct ReloadableSombreroHandle {
+    current: Arc<RwLock<Arc<PreparedSombrero>>>,
+}
+
+impl ReloadableSombreroHandle {
+    /// Use `sombrero` for every request from now on. Requests in flight keep the old config.
+    pub fn update(&self, sombrero: Sombrero) {
+        let prepared = PreparedSombrero::new(sombrero);
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = prepared;
+    }
+
+    /// A copy of the current config.
+    pub fn get(&self) -> Sombrero {
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .sombrero
+            .clone()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReloadableSombreroService<S> {
+    current: Arc<RwLock<Arc<PreparedSombrero>>>,
+    inner: S,
+}
+
+impl<S, Body> Service<Request<Body>> for ReloadableSombreroService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Future: Send + 'static,
+    S::Error: 'static,
+    Body: Send + 'static,
+{
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+    type Response = Response<Body>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let prepared = Arc::clone(&self.current.read().unwrap_or_else(PoisonError::into_inner));
+        prepared.call_service(&mut self.inner, request)
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::extract::MatchedPath;
+use futures_util::future::BoxFuture;
+use http::{Request, Response};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{PreparedSombrero, Sombrero};
+
+/// A layer picking a different [`Sombrero`] per axum route.
+///
+/// Routes are matched on the route pattern (axum's [`MatchedPath`]), like `/users/{id}`,
+/// not on the request path. Requests without a registered pattern use the default.
+/// This layer must be added with [`axum::Router::layer`] so the matched path is available.
+#[derive(Debug, Clone)]
+pub struct RoutedSombrero {
+    default: Arc<PreparedSombrero>,
+    routes: Arc<HashMap<String, Arc<PreparedSombrero>>>,
+}
+
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct RoutedSombreroBuilder {
+    default: Sombrero,
+    routes: HashMap<String, Sombrero>,
+}
+
+impl RoutedSombrero {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(default: Sombrero) -> RoutedSombreroBuilder {
+        RoutedSombreroBuilder {
+            default,
+            routes: HashMap::new(),
+        }
+    }
+}
+
+impl RoutedSombreroBuilder {
+    /// Use `sombrero` for the route registered as `path`.
+    pub fn route(mut self, path: &str, sombrero: Sombrero) -> Self {
+        self.routes.insert(path.to_string(), sombrero);
+        self
+    }
+
+    pub fn build(self) -> RoutedSombrero {
+        let routes = self
+            .routes
+            .into_iter()
+            .map(|(path, sombrero)| (path, PreparedSombrero::new(sombrero)))
+            .collect();
+        RoutedSombrero {
+            default: PreparedSombrero::new(self.default),
+            routes: Arc::new(routes),
+        }
+    }
+}
+
+impl<S> Layer<S> for RoutedSombrero {
+    type Service = RoutedSombreroService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RoutedSombreroService {
+            routed: self.clone(),
+            inner,
+        }
+    }
+}
+
+impl<S> Layer<S> for RoutedSombreroBuilder {
+    type Service = RoutedSombreroService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        self.clone().build().layer(inner)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RoutedSombreroService<S> {
+    routed: RoutedSombrero,
+    inner: S,
+}
+
+impl<S, Body> Service<Request<Body>> for RoutedSombreroService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Future: Send + 'static,
+    S::Error: 'static,
+    Body: Send + 'static,
+{
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+    type Response = Response<Body>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let sombrero = request
+            .extensions()
+            .get::<MatchedPath>()
+            .and_then(|path| self.routed.routes.get(path.as_str()))
+            .unwrap_or(&self.routed.default);
+        sombrero.call_service(&mut self.inner, request)
+    }
+}
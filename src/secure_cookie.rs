@@ -0,0 +1,115 @@
+use std::task::{Context, Poll};
+
+use axum_extra::extract::cookie::Cookie;
+use futures_util::future::BoxFuture;
+use http::{header::SET_COOKIE, uri::Scheme, HeaderMap, Request, Response};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{Sombrero, SombreroService};
+
+/// [`Sombrero`] with [`Sombrero::enforce_secure_cookies_with_sts`], which also logs a warning
+/// whenever a `Secure` cookie is set on a plain HTTP response, where browsers will drop it.
+///
+/// Requests count as plain HTTP when `X-Forwarded-Proto` is `http`, or, without that header,
+/// when the request URI has the `http` scheme.
+#[derive(Debug, Clone)]
+pub struct SecureCookieSombreroLayer {
+    sombrero: Sombrero,
+}
+
+impl SecureCookieSombreroLayer {
+    pub fn new(sombrero: Sombrero) -> Self {
+        Self {
+            sombrero: sombrero.enforce_secure_cookies_with_sts(true),
+        }
+    }
+}
+
+impl<S> Layer<S> for SecureCookieSombreroLayer {
+    type Service = SombreroService<SecureCookieService<S>>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        self.sombrero.layer(SecureCookieService { inner })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SecureCookieService<S> {
+    inner: S,
+}
+
+impl<S, Body> Service<Request<Body>> for SecureCookieService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Future: Send + 'static,
+    S::Error: 'static,
+    Body: Send + 'static,
+{
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+    type Response = Response<Body>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let plain_http = is_plain_http(&request);
+        let future = self.inner.call(request);
+        Box::pin(async move {
+            let response = future.await?;
+            if plain_http {
+                for name in secure_cookie_names(response.headers()) {
+                    tracing::warn!(
+                        cookie = name,
+                        "`Secure` cookie set over plain HTTP; browsers will ignore it"
+                    );
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+fn is_plain_http<B>(request: &Request<B>) -> bool {
+    request.headers().get("x-forwarded-proto").map_or_else(
+        || request.uri().scheme() == Some(&Scheme::HTTP),
+        |proto| proto.as_bytes().eq_ignore_ascii_case(b"http"),
+    )
+}
+
+/// The names of the cookies `headers` sets with the `Secure` attribute.
+fn secure_cookie_names(headers: &HeaderMap) -> Vec<String> {
+    headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| Cookie::parse(value.to_str().ok()?).ok())
+        .filter(|cookie| cookie.secure() == Some(true))
+        .map(|cookie| cookie.name().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+#[test]
+fn detects_secure_cookies_over_http() {
+    let forwarded = Request::get("/")
+        .header("x-forwarded-proto", "http")
+        .body(())
+        .unwrap();
+    assert!(is_plain_http(&forwarded));
+    let forwarded_https = Request::get("http://example.com/")
+        .header("x-forwarded-proto", "https")
+        .body(())
+        .unwrap();
+    assert!(!is_plain_http(&forwarded_https));
+    assert!(is_plain_http(
+        &Request::get("http://example.com/").body(()).unwrap()
+    ));
+    assert!(!is_plain_http(&Request::get("/").body(()).unwrap()));
+
+    let mut headers = HeaderMap::new();
+    headers.append(SET_COOKIE, "session=abc; Secure; HttpOnly".parse().unwrap());
+    headers.append(SET_COOKIE, "theme=dark".parse().unwrap());
+    assert_eq!(secure_cookie_names(&headers), ["session"]);
+}
@@ -0,0 +1,92 @@
+//! A report on a [`Sombrero`] config in the shape of the [securityheaders.com] scanner.
+//!
+//! The scanner grades six headers. Only whether they are set is checked here, not their values.
+//!
+//! [securityheaders.com]: https://securityheaders.com
+
+use http::HeaderMap;
+
+use crate::Sombrero;
+
+impl Sombrero {
+    /// The headers graded by securityheaders.com which this config does not set.
+    pub fn missing_headers(&self) -> Vec<&'static str> {
+        [
+            (
+                "Content-Security-Policy",
+                self.content_security_policy.is_some(),
+            ),
+            (
+                "Strict-Transport-Security",
+                self.strict_transport_security.is_some(),
+            ),
+            ("X-Frame-Options", self.x_frame_options.is_some()),
+            (
+                "X-Content-Type-Options",
+                self.x_content_type_options.is_some(),
+            ),
+            ("Referrer-Policy", self.referrer_policy.is_some()),
+            ("Permissions-Policy", self.permissions_policy.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, set)| !set)
+        .map(|(name, _)| name)
+        .collect()
+    }
+
+    /// Every header this config applies with `nonce`, the [missing ones](Self::missing_headers),
+    /// and a `score` out of 100 for the share of graded headers that are set, as JSON:
+    ///
+    /// ```json
+    /// {"headers":{"x-frame-options":"SAMEORIGIN"},"missing":["Permissions-Policy"],"score":83}
+    /// ```
+    ///
+    /// Repeated headers are joined with `, `.
+    pub fn to_security_scanner_json(&self, nonce: &str) -> String {
+        const GRADED_HEADERS: usize = 6;
+
+        let mut headers = HeaderMap::new();
+        self.insert_headers(&mut headers, nonce);
+        let missing = self.missing_headers();
+
+        let mut json = String::from("{\"headers\":{");
+        for (i, name) in headers.keys().enumerate() {
+            if i != 0 {
+                json.push(',');
+            }
+            let values: Vec<_> = headers
+                .get_all(name)
+                .iter()
+                .map(|value| String::from_utf8_lossy(value.as_bytes()))
+                .collect();
+            push_json_string(&mut json, name.as_str());
+            json.push(':');
+            push_json_string(&mut json, &values.join(", "));
+        }
+        json.push_str("},\"missing\":[");
+        for (i, name) in missing.iter().enumerate() {
+            if i != 0 {
+                json.push(',');
+            }
+            push_json_string(&mut json, name);
+        }
+        let score = (GRADED_HEADERS - missing.len()) * 100 / GRADED_HEADERS;
+        json.push_str("],\"score\":");
+        json.push_str(&score.to_string());
+        json.push('}');
+        json
+    }
+}
+
+fn push_json_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
@@ -0,0 +1,215 @@
+//! Comparing [`Sombrero`] configs by how much they allow.
+//!
+//! `a < b` means `a` is strictly more permissive than `b`: `b` keeps every restriction `a` has,
+//! and adds more. Configs that each allow something the other doesn't are incomparable.
+//!
+//! Headers are compared one at a time. A disabled header is more permissive than an enabled
+//! one, and enabled headers are ranked by the strength of their value. The enforced CSP is
+//! compared with [`ContentSecurityPolicy::is_strictly_more_permissive_than`]. Values with no
+//! meaningful ranking, like `X-XSS-Protection` or `Reporting-Endpoints`, are only comparable
+//! when equal. `Cache-Control: no-store` is stricter than caching being allowed, and
+//! `Access-Control-Expose-Headers` is only comparable when both configs agree on it. Other
+//! options, which change when or how headers are sent, are not compared. This includes
+//! [`Sombrero::cross_origin_resource_policy_when`], whose path predicates can't be compared.
+
+use std::{cmp::Ordering, sync::Arc};
+
+use crate::{
+    headers::{
+        ContentSecurityPolicy, CrossOriginEmbedderPolicy, CrossOriginOpenerPolicy,
+        CrossOriginResourcePolicy, ReferrerPolicy, StrictTransportSecurity, XDnsPrefetchControl,
+        XFrameOptions, XPermittedCrossDomainPolicies,
+    },
+    Sombrero,
+};
+
+/// Two configs are equal when they send the same headers.
+impl PartialEq for Sombrero {
+    #[allow(deprecated)]
+    fn eq(&self, other: &Self) -> bool {
+        self.content_security_policy == other.content_security_policy
+            && self.content_security_policy_report_only == other.content_security_policy_report_only
+            && self.cross_origin_embedder_policy == other.cross_origin_embedder_policy
+            && self.cross_origin_opener_policy == other.cross_origin_opener_policy
+            && self.cross_origin_resource_policy == other.cross_origin_resource_policy
+            && self.origin_agent_cluster == other.origin_agent_cluster
+            && self.referrer_policy == other.referrer_policy
+            && self.strict_transport_security == other.strict_transport_security
+            && self.x_content_type_options == other.x_content_type_options
+            && self.x_dns_prefetch_control == other.x_dns_prefetch_control
+            && self.x_download_options == other.x_download_options
+            && self.x_frame_options == other.x_frame_options
+            && self.x_permitted_cross_domain_policies == other.x_permitted_cross_domain_policies
+            && self.x_xss_protection == other.x_xss_protection
+            && self.expect_ct == other.expect_ct
+            && self.reporting_endpoints == other.reporting_endpoints
+            && self.permissions_policy == other.permissions_policy
+            && self.csp_header_name == other.csp_header_name
+            && self.sends_no_store() == other.sends_no_store()
+            && self.sends_exposed_csp() == other.sends_exposed_csp()
+    }
+}
+
+/// See the [module docs](crate::strictness).
+impl PartialOrd for Sombrero {
+    #[allow(deprecated)]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let orderings = [
+            csp(
+                &self.content_security_policy,
+                &other.content_security_policy,
+            ),
+            optional(
+                self.cross_origin_embedder_policy,
+                other.cross_origin_embedder_policy,
+                |v| match v {
+                    CrossOriginEmbedderPolicy::UnsafeNone => 0,
+                    CrossOriginEmbedderPolicy::Credentialless => 1,
+                    CrossOriginEmbedderPolicy::RequireCorp => 2,
+                },
+            ),
+            optional(
+                self.cross_origin_opener_policy,
+                other.cross_origin_opener_policy,
+                |v| match v {
+                    CrossOriginOpenerPolicy::UnsafeNone => 0,
+                    CrossOriginOpenerPolicy::SameOriginAllowPopups => 1,
+                    CrossOriginOpenerPolicy::SameOrigin => 2,
+                },
+            ),
+            optional(
+                self.cross_origin_resource_policy,
+                other.cross_origin_resource_policy,
+                |v| match v {
+                    CrossOriginResourcePolicy::CrossOrigin => 0,
+                    CrossOriginResourcePolicy::SameSite => 1,
+                    CrossOriginResourcePolicy::SameOrigin => 2,
+                },
+            ),
+            optional(self.origin_agent_cluster, other.origin_agent_cluster, |_| 0),
+            optional(self.referrer_policy, other.referrer_policy, referrer_rank),
+            sts(
+                self.strict_transport_security,
+                other.strict_transport_security,
+            ),
+            optional(
+                self.x_content_type_options,
+                other.x_content_type_options,
+                |_| 0,
+            ),
+            optional(
+                self.x_dns_prefetch_control,
+                other.x_dns_prefetch_control,
+                |v| match v {
+                    XDnsPrefetchControl::On => 0,
+                    XDnsPrefetchControl::Off => 1,
+                },
+            ),
+            optional(self.x_download_options, other.x_download_options, |_| 0),
+            optional(self.x_frame_options, other.x_frame_options, |v| match v {
+                XFrameOptions::Sameorigin => 0,
+                XFrameOptions::Deny => 1,
+            }),
+            optional(
+                self.x_permitted_cross_domain_policies,
+                other.x_permitted_cross_domain_policies,
+                |v| match v {
+                    XPermittedCrossDomainPolicies::All => 0,
+                    XPermittedCrossDomainPolicies::ByContentType => 1,
+                    XPermittedCrossDomainPolicies::MasterOnly => 2,
+                    XPermittedCrossDomainPolicies::None => 3,
+                },
+            ),
+            optional(self.x_xss_protection, other.x_xss_protection, |_| 0),
+            optional(self.expect_ct.as_ref(), other.expect_ct.as_ref(), |_| 0),
+            optional(
+                self.reporting_endpoints.as_ref(),
+                other.reporting_endpoints.as_ref(),
+                |_| 0,
+            ),
+            optional(
+                self.permissions_policy.as_ref(),
+                other.permissions_policy.as_ref(),
+                |_| 0,
+            ),
+            optional(
+                self.sends_no_store().then_some(()),
+                other.sends_no_store().then_some(()),
+                |()| 0,
+            ),
+            (self.sends_exposed_csp() == other.sends_exposed_csp()).then_some(Ordering::Equal),
+        ];
+        let combined =
+            orderings
+                .into_iter()
+                .try_fold(Ordering::Equal, |combined, ordering| {
+                    match (combined, ordering?) {
+                        (combined, Ordering::Equal) => Some(combined),
+                        (Ordering::Equal, ordering) => Some(ordering),
+                        (combined, ordering) => (combined == ordering).then_some(combined),
+                    }
+                })?;
+        // equally strict, but different, like a CSP spelling out what default-src implies
+        if combined == Ordering::Equal && self != other {
+            return None;
+        }
+        Some(combined)
+    }
+}
+
+/// A disabled header is more permissive than an enabled one. Different values
+/// with the same `rank` are incomparable.
+fn optional<T: PartialEq>(a: Option<T>, b: Option<T>, rank: fn(&T) -> u8) -> Option<Ordering> {
+    match (a, b) {
+        (None, None) => Some(Ordering::Equal),
+        (None, Some(_)) => Some(Ordering::Less),
+        (Some(_), None) => Some(Ordering::Greater),
+        (Some(a), Some(b)) if a == b => Some(Ordering::Equal),
+        (Some(a), Some(b)) => match rank(&a).cmp(&rank(&b)) {
+            Ordering::Equal => None,
+            ordering => Some(ordering),
+        },
+    }
+}
+
+const fn referrer_rank(policy: &ReferrerPolicy) -> u8 {
+    match policy {
+        ReferrerPolicy::UnsafeUrl => 0,
+        ReferrerPolicy::NoReferrerWhenDowngrade => 1,
+        ReferrerPolicy::OriginWhenCrossOrigin => 2,
+        ReferrerPolicy::StrictOriginWhenCrossOrigin | ReferrerPolicy::Origin => 3,
+        ReferrerPolicy::SameOrigin | ReferrerPolicy::StrictOrigin => 4,
+        ReferrerPolicy::NoReferrer => 5,
+    }
+}
+
+/// A longer `max-age` and `includeSubDomains` are each stricter.
+fn sts(a: Option<StrictTransportSecurity>, b: Option<StrictTransportSecurity>) -> Option<Ordering> {
+    let (Some(a), Some(b)) = (a, b) else {
+        return optional(a, b, |_| 0);
+    };
+    let max_age = a.max_age.cmp(&b.max_age);
+    let sub_domains = a.include_sub_domains.cmp(&b.include_sub_domains);
+    match (max_age, sub_domains) {
+        (ordering, Ordering::Equal) | (Ordering::Equal, ordering) => Some(ordering),
+        (max_age, sub_domains) => (max_age == sub_domains).then_some(max_age),
+    }
+}
+
+fn csp(
+    a: &Option<Arc<ContentSecurityPolicy>>,
+    b: &Option<Arc<ContentSecurityPolicy>>,
+) -> Option<Ordering> {
+    let (Some(a), Some(b)) = (a, b) else {
+        return optional(a.as_ref(), b.as_ref(), |_| 0);
+    };
+    match (
+        a.is_at_least_as_permissive_as(b),
+        b.is_at_least_as_permissive_as(a),
+    ) {
+        (true, true) => Some(Ordering::Equal),
+        (true, false) => Some(Ordering::Less),
+        (false, true) => Some(Ordering::Greater),
+        (false, false) => None,
+    }
+}
@@ -0,0 +1,152 @@
+//! Helpers for testing [`Sombrero`] configs without running a server.
+
+use std::convert::Infallible;
+
+use futures_util::FutureExt;
+use http::{Request, Response};
+use tower::{service_fn, Layer, ServiceExt};
+
+use crate::{
+    headers::{ContentSecurityPolicy, XFrameOptions},
+    Sombrero,
+};
+
+/// Run `request` through `sombrero` wrapped around a service that returns an empty response,
+/// and return the response with the security headers applied.
+pub async fn apply_sombrero_oneshot<B>(sombrero: Sombrero, request: Request<B>) -> Response<B>
+where
+    B: Default + Send + 'static,
+{
+    let inner = service_fn(|_req: Request<B>| async { Ok::<_, Infallible>(Response::default()) });
+    match sombrero.layer(inner).oneshot(request).await {
+        Ok(response) => response,
+        Err(infallible) => match infallible {},
+    }
+}
+
+/// Configs paired with every header they are expected to add, for checking many configs at once.
+/// See [`assert_fixture!`](crate::assert_fixture).
+#[derive(Debug, Clone, Default)]
+pub struct SombreroFixture {
+    pub cases: Vec<(Sombrero, Vec<(&'static str, &'static str)>)>,
+}
+
+const DEFAULT_CSP: &str = "default-src 'self';font-src 'self' https: data:;\
+    img-src 'self' data:;object-src 'none';script-src 'self';script-src-attr 'none';\
+    style-src 'self' https: 'unsafe-inline';base-uri 'self';form-action 'self';\
+    frame-ancestors 'self';upgrade-insecure-requests;";
+
+const LOCKED_DOWN_CSP: &str = "default-src 'none';connect-src 'self';img-src 'self';\
+    script-src 'self';style-src 'self';base-uri 'none';form-action 'none';\
+    frame-ancestors 'none';";
+
+impl SombreroFixture {
+    /// The default config, everything disabled, a CSP alone, a report-only CSP alone,
+    /// and a strict config.
+    pub fn standard() -> Self {
+        let default_headers = |x_frame_options, csp| {
+            vec![
+                ("content-security-policy", csp),
+                ("cross-origin-opener-policy", "same-origin"),
+                ("cross-origin-resource-policy", "same-origin"),
+                ("origin-agent-cluster", "?1"),
+                ("referrer-policy", "no-referrer"),
+                (
+                    "strict-transport-security",
+                    "max-age=15552000;includeSubDomains",
+                ),
+                ("x-content-type-options", "nosniff"),
+                ("x-download-options", "noopen"),
+                ("x-frame-options", x_frame_options),
+                ("x-permitted-cross-domain-policies", "none"),
+                ("x-xss-protection", "0"),
+            ]
+        };
+        let strict = Sombrero::default()
+            .content_security_policy(ContentSecurityPolicy::locked_down())
+            .x_frame_options(XFrameOptions::Deny);
+        Self::default()
+            .case(
+                Sombrero::default(),
+                default_headers("SAMEORIGIN", DEFAULT_CSP),
+            )
+            .case(Sombrero::new_empty(), [])
+            .case(
+                Sombrero::new_empty()
+                    .content_security_policy(ContentSecurityPolicy::strict_default()),
+                [("content-security-policy", DEFAULT_CSP)],
+            )
+            .case(
+                Sombrero::new_empty()
+                    .content_security_policy_report_only(ContentSecurityPolicy::locked_down()),
+                [("content-security-policy-report-only", LOCKED_DOWN_CSP)],
+            )
+            .case(strict, default_headers("DENY", LOCKED_DOWN_CSP))
+    }
+
+    /// Add a config and the headers it should produce.
+    #[must_use]
+    pub fn case(
+        mut self,
+        sombrero: Sombrero,
+        expected_headers: impl IntoIterator<Item = (&'static str, &'static str)>,
+    ) -> Self {
+        self.cases
+            .push((sombrero, expected_headers.into_iter().collect()));
+        self
+    }
+
+    /// Call `f` with every config and its expected headers.
+    pub fn run_all(&self, f: impl Fn(Sombrero, Vec<(&str, &str)>)) {
+        for (sombrero, expected) in &self.cases {
+            f(sombrero.clone(), expected.clone());
+        }
+    }
+
+    /// Apply every config to an empty response, and panic unless it gets exactly the
+    /// expected headers.
+    pub fn assert_all(&self) {
+        self.run_all(|sombrero, expected| {
+            let response = apply_sombrero_oneshot(sombrero, Request::new(()))
+                .now_or_never()
+                .expect("applying a sombrero to an empty response never waits");
+            let headers = response.headers();
+            for (name, value) in &expected {
+                assert_eq!(
+                    headers.get(*name).and_then(|v| v.to_str().ok()),
+                    Some(*value),
+                    "{name}"
+                );
+            }
+            assert_eq!(
+                headers.len(),
+                expected.len(),
+                "unexpected headers in {headers:?}"
+            );
+        });
+    }
+}
+
+/// Declare a test checking every case of a [`SombreroFixture`], by default
+/// [`SombreroFixture::standard`].
+///
+/// ```rust
+/// # use tower_sombrero::{assert_fixture, testing::SombreroFixture, Sombrero};
+/// assert_fixture!(standard_configs);
+/// assert_fixture!(
+///     empty_config,
+///     SombreroFixture::default().case(Sombrero::new_empty(), [])
+/// );
+/// ```
+#[macro_export]
+macro_rules! assert_fixture {
+    ($name:ident) => {
+        $crate::assert_fixture!($name, $crate::testing::SombreroFixture::standard());
+    };
+    ($name:ident, $fixture:expr) => {
+        #[test]
+        fn $name() {
+            $fixture.assert_all();
+        }
+    };
+}
@@ -1,9 +1,14 @@
-use axum::{routing::get, Router};
+use axum::{http::StatusCode, routing::get, Extension, Router};
 use tokio::{net::TcpListener, sync::oneshot::Sender, task::JoinHandle};
 
 use crate::{
-    headers::{ContentSecurityPolicy, CspSource},
-    Sombrero,
+    csp::{CspHashes, CspLayer, OwaspCspWarning},
+    headers::{
+        ContentSecurityPolicy, CspHashAlgorithm, CspSchemeSource, CspSource, ReferrerPolicy,
+        ResponseBuilderExt, XContentTypeOptions,
+    },
+    testing::apply_sombrero_oneshot,
+    Sombrero, SombreroCell,
 };
 
 #[tokio::test]
@@ -31,7 +36,90 @@ async fn sombrero_layer_handles() {
 
 #[tokio::test]
 async fn sombrero_layer_adds_csp() {
-    let sombrero = Sombrero::default();
+    let resp = apply_sombrero_oneshot(Sombrero::default(), http::Request::new(())).await;
+    let csp = resp
+        .headers()
+        .get("content-security-policy")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    eprintln!("{csp}");
+    assert!(csp.contains("default-src 'self';"));
+    assert!(csp.contains("base-uri 'self';"));
+}
+
+#[tokio::test]
+async fn sombrero_layer_changes_csp_nonce() {
+    let csp = ContentSecurityPolicy::new_empty().script_src(CspSource::Nonce);
+    let sombrero = Sombrero::new_empty().content_security_policy(csp);
+    let resp1 = apply_sombrero_oneshot(sombrero.clone(), http::Request::new(())).await;
+    let resp2 = apply_sombrero_oneshot(sombrero, http::Request::new(())).await;
+    assert_ne!(
+        resp1.headers()["content-security-policy"],
+        resp2.headers()["content-security-policy"]
+    );
+}
+
+#[tokio::test]
+async fn sombrero_layer_one_nonce_per_request() {
+    let csp = ContentSecurityPolicy::new_empty().script_src([CspSource::Nonce]);
+    let sombrero = Sombrero::new_empty()
+        .content_security_policy(csp.clone())
+        .content_security_policy_report_only(csp);
+    let resp = apply_sombrero_oneshot(sombrero, http::Request::new(())).await;
+    assert_eq!(
+        resp.headers()["content-security-policy"],
+        resp.headers()["content-security-policy-report-only"]
+    );
+}
+
+#[tokio::test]
+async fn oneshot_matches_live_server() {
+    let server = test_server(Sombrero::default()).await;
+    let live = reqwest::get(server.url()).await.unwrap();
+    let oneshot = apply_sombrero_oneshot(Sombrero::default(), http::Request::new(())).await;
+    for (name, value) in oneshot.headers() {
+        assert_eq!(live.headers().get(name), Some(value), "{name}");
+    }
+    server.shutdown().await;
+}
+
+#[test]
+fn strict_default_is_owasp_compliant() {
+    let sombrero =
+        Sombrero::new_empty().content_security_policy(ContentSecurityPolicy::strict_default());
+    assert_eq!(sombrero.owasp_conformance_check(), vec![]);
+    assert!(sombrero.is_owasp_compliant());
+}
+
+#[test]
+fn broken_csp_fails_owasp_checks() {
+    let csp = ContentSecurityPolicy::strict_default()
+        .script_src([CspSource::SelfOrigin, CspSource::UnsafeInline])
+        .remove_object_src()
+        .upgrade_insecure_requests(false);
+    let sombrero = Sombrero::new_empty().content_security_policy(csp);
+    assert_eq!(
+        sombrero.owasp_conformance_check(),
+        vec![
+            OwaspCspWarning::UnsafeInlineInEnforcedScriptSrc,
+            OwaspCspWarning::MissingObjectSrcNone,
+            OwaspCspWarning::MissingUpgradeInsecureRequests,
+        ]
+    );
+    assert!(!sombrero.is_owasp_compliant());
+}
+
+#[test]
+fn missing_csp_fails_owasp_checks() {
+    let warnings = Sombrero::new_empty().owasp_conformance_check();
+    assert!(warnings.contains(&OwaspCspWarning::NoDefaultSrcOrScriptSrc));
+    assert!(warnings.contains(&OwaspCspWarning::MissingBaseUriSelf));
+}
+
+#[tokio::test]
+async fn sombrero_saml_sso_preset() {
+    let sombrero = Sombrero::for_saml_sso("https://idp.example.com/sso");
     let server = test_server(sombrero).await;
     let resp = reqwest::get(server.url()).await.unwrap();
     let csp = resp
@@ -40,53 +128,2349 @@ async fn sombrero_layer_adds_csp() {
         .unwrap()
         .to_str()
         .unwrap();
-    eprintln!("{csp}");
-    assert!(csp.contains("default-src 'self';"));
-    assert!(csp.contains("base-uri 'self';"));
-    server.shutdown().await;
+    assert!(csp.contains("form-action 'self' https://idp.example.com/sso;"));
+    assert!(csp.contains("frame-ancestors 'none';"));
+    assert_eq!(resp.headers().get("x-frame-options").unwrap(), "DENY");
+    server.shutdown().await;
+}
+
+#[test]
+fn sombrero_csp_accessor() {
+    let sombrero = Sombrero::default();
+    let csp = sombrero.csp().unwrap().value("").unwrap();
+    assert!(csp.to_str().unwrap().contains("default-src 'self';"));
+    assert!(sombrero.csp_report_only().is_none());
+}
+
+#[tokio::test]
+async fn sombrero_skips_csp_on_rate_limit() {
+    let sombrero = Sombrero::default().skip_csp_on_rate_limit(true);
+    let app = Router::new()
+        .route("/", get(rate_limited_handler))
+        .layer(sombrero);
+    let server = test_server_router(app).await;
+    let resp = reqwest::get(server.url()).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(resp.headers().get("content-security-policy").is_none());
+    assert!(resp.headers().get("x-frame-options").is_none());
+    assert!(resp.headers().get("strict-transport-security").is_some());
+    assert!(resp.headers().get("x-content-type-options").is_some());
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn sombrero_keeps_csp_on_rate_limit_by_default() {
+    let app = Router::new()
+        .route("/", get(rate_limited_handler))
+        .layer(Sombrero::default());
+    let server = test_server_router(app).await;
+    let resp = reqwest::get(server.url()).await.unwrap();
+    assert!(resp.headers().get("content-security-policy").is_some());
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn oneshot_rate_limited_response() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    let service = |sombrero: Sombrero| {
+        sombrero.layer(service_fn(|_: http::Request<()>| async {
+            let response = http::Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .header("retry-after", "1")
+                .body(())
+                .unwrap();
+            Ok::<_, std::convert::Infallible>(response)
+        }))
+    };
+
+    let skipped = service(Sombrero::default().skip_csp_on_rate_limit(true))
+        .oneshot(http::Request::new(()))
+        .await
+        .unwrap();
+    let headers = skipped.headers();
+    assert!(!headers.contains_key("content-security-policy"));
+    assert!(!headers.contains_key("x-frame-options"));
+    assert!(headers.contains_key("strict-transport-security"));
+    assert_eq!(headers["x-content-type-options"], "nosniff");
+
+    let kept = service(Sombrero::default())
+        .oneshot(http::Request::new(()))
+        .await
+        .unwrap();
+    assert!(kept.headers().contains_key("content-security-policy"));
+}
+
+#[tokio::test]
+async fn oneshot_nonce_extension_matches_header() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    use crate::csp::CspNonce;
+
+    let csp = ContentSecurityPolicy::new_empty().script_src([CspSource::Nonce]);
+    let service = Sombrero::new_empty()
+        .content_security_policy(csp)
+        .layer(service_fn(|request: http::Request<()>| async move {
+            let nonce = request.extensions().get::<CspNonce>().unwrap();
+            let response = http::Response::builder()
+                .header("x-nonce", nonce.as_str())
+                .body(())
+                .unwrap();
+            Ok::<_, std::convert::Infallible>(response)
+        }));
+    let response = service.oneshot(http::Request::new(())).await.unwrap();
+    let nonce = response.headers()["x-nonce"].to_str().unwrap();
+    assert_eq!(nonce.len(), 32);
+    assert_eq!(
+        response.headers()["content-security-policy"],
+        format!("script-src 'nonce-{nonce}';")
+    );
+}
+
+#[tokio::test]
+async fn oneshot_appends_request_hashes() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    let service = Sombrero::default().layer(service_fn(|request: http::Request<()>| async move {
+        let hashes = request.extensions().get::<CspHashes>().unwrap();
+        hashes.push(CspHashAlgorithm::Sha256, "abc123");
+        Ok::<_, std::convert::Infallible>(http::Response::new(()))
+    }));
+    let response = service.oneshot(http::Request::new(())).await.unwrap();
+    let csp = response.headers()["content-security-policy"]
+        .to_str()
+        .unwrap();
+    assert!(csp.contains("script-src 'self' 'sha256-abc123';"));
+}
+
+#[tokio::test]
+async fn invalid_policy_fails_closed() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    let csp = ContentSecurityPolicy::strict_default().report_uri("/csp; script-src *");
+    let response = apply_sombrero_oneshot(
+        Sombrero::default().content_security_policy(csp.clone()),
+        http::Request::new(()),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(
+        response.headers()["content-security-policy"],
+        "default-src 'none';sandbox;"
+    );
+    assert_eq!(response.headers()["x-content-type-options"], "nosniff");
+
+    let service = CspLayer::new(csp).layer(service_fn(|_: http::Request<()>| async {
+        Ok::<_, std::convert::Infallible>(http::Response::new(()))
+    }));
+    let response = service.oneshot(http::Request::new(())).await.unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(
+        response.headers()["content-security-policy"],
+        "default-src 'none';sandbox;"
+    );
+
+    let valid = ContentSecurityPolicy::strict_default().report_uri("/csp");
+    let response = apply_sombrero_oneshot(
+        Sombrero::default().content_security_policy(valid),
+        http::Request::new(()),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let value = response.headers()["content-security-policy"]
+        .to_str()
+        .unwrap();
+    assert!(value.ends_with("report-uri /csp;"), "{value}");
+}
+
+#[test]
+fn response_builder_security_headers() {
+    let resp = http::Response::builder()
+        .security_header(XContentTypeOptions)
+        .security_header(ReferrerPolicy::SameOrigin)
+        .body(())
+        .unwrap();
+    assert_eq!(resp.headers()["x-content-type-options"], "nosniff");
+    assert_eq!(resp.headers()["referrer-policy"], "same-origin");
+}
+
+#[test]
+fn response_builder_sombrero() {
+    let csp = ContentSecurityPolicy::new_empty().script_src(CspSource::Nonce);
+    let sombrero = Sombrero::default().content_security_policy(csp);
+    let resp = http::Response::builder()
+        .sombrero(&sombrero, "abc")
+        .body(())
+        .unwrap();
+    assert_eq!(
+        resp.headers()["content-security-policy"],
+        "script-src 'nonce-abc';"
+    );
+    assert_eq!(resp.headers()["x-frame-options"], "SAMEORIGIN");
+}
+
+#[test]
+fn sombrero_cell_initializes_once() {
+    let cell = SombreroCell::new();
+    assert!(cell.get().is_none());
+    let sombrero = cell.get_or_init(Sombrero::new_empty);
+    assert!(sombrero.csp().is_none());
+    let sombrero = cell.get_or_init(Sombrero::default);
+    assert!(sombrero.csp().is_none());
+}
+
+#[test]
+fn sombrero_cell_rejects_double_set() {
+    let cell = SombreroCell::new();
+    assert!(cell.set(Sombrero::default()).is_ok());
+    let rejected = cell.set(Sombrero::new_empty()).unwrap_err();
+    assert!(rejected.csp().is_none());
+    assert!(cell.get().unwrap().csp().is_some());
+}
+
+#[test]
+fn sombrero_cell_concurrent_access() {
+    static CELL: SombreroCell = SombreroCell::new();
+    let pointers: Vec<usize> = std::thread::scope(|scope| {
+        let mut handles = Vec::new();
+        for _ in 0..8 {
+            handles.push(
+                scope.spawn(|| std::ptr::from_ref(CELL.get_or_init(Sombrero::default)) as usize),
+            );
+        }
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    assert!(pointers.windows(2).all(|w| w[0] == w[1]));
+}
+
+#[tokio::test]
+async fn sombrero_cell_layer() {
+    static CELL: SombreroCell = SombreroCell::new();
+    let app = Router::new().route("/", get(test_handler)).layer(&CELL);
+    let server = test_server_router(app).await;
+    let resp = reqwest::get(server.url()).await.unwrap();
+    assert!(resp.headers().get("content-security-policy").is_some());
+    server.shutdown().await;
+}
+
+#[test]
+fn sombrero_pushed_resource_subset() {
+    let mut resp = http::Response::new(());
+    Sombrero::default().apply_to_pushed_resource(&mut resp);
+    let headers = resp.headers();
+    assert_eq!(headers["x-content-type-options"], "nosniff");
+    assert!(headers.contains_key("strict-transport-security"));
+    assert_eq!(headers["cross-origin-resource-policy"], "same-origin");
+    assert!(!headers.contains_key("content-security-policy"));
+    assert!(!headers.contains_key("x-frame-options"));
+    assert_eq!(headers.len(), 3);
+
+    // assets keep their own caching, even with `no_store`
+    let mut resp = http::Response::builder()
+        .header("cache-control", "public, max-age=31536000, immutable")
+        .body(())
+        .unwrap();
+    Sombrero::default()
+        .no_store(true)
+        .apply_to_pushed_resource(&mut resp);
+    assert_eq!(
+        resp.headers()["cache-control"],
+        "public, max-age=31536000, immutable"
+    );
+}
+
+#[test]
+#[allow(deprecated)]
+fn sombrero_expect_ct() {
+    let expect_ct = crate::headers::ExpectCt {
+        max_age: 60,
+        enforce: true,
+        report_uri: None,
+    };
+    let sombrero = Sombrero::new_empty().expect_ct(expect_ct);
+    let resp = http::Response::builder()
+        .sombrero(&sombrero, "")
+        .body(())
+        .unwrap();
+    assert_eq!(resp.headers()["expect-ct"], "max-age=60, enforce");
+    let resp = http::Response::builder()
+        .sombrero(&sombrero.remove_expect_ct(), "")
+        .body(())
+        .unwrap();
+    assert!(resp.headers().is_empty());
+}
+
+#[tokio::test]
+async fn sombrero_appends_request_hashes() {
+    async fn handler(Extension(hashes): Extension<CspHashes>) -> &'static str {
+        hashes.push(CspHashAlgorithm::Sha256, "abc123");
+        "<script>alert(1)</script>"
+    }
+    let app = Router::new()
+        .route("/", get(handler))
+        .layer(Sombrero::default());
+    let server = test_server_router(app).await;
+    let resp = reqwest::get(server.url()).await.unwrap();
+    let csp = resp.headers()["content-security-policy"].to_str().unwrap();
+    assert!(csp.contains("script-src 'self' 'sha256-abc123';"));
+    server.shutdown().await;
+}
+
+#[test]
+fn sombrero_form_action_self_only() {
+    let sombrero = Sombrero::new_empty().form_action_self_only();
+    let csp = sombrero.csp().unwrap().value("").unwrap();
+    assert_eq!(csp, "form-action 'self';");
+
+    let sombrero = Sombrero::default().form_action_self_only();
+    let csp = sombrero.csp().unwrap().value("").unwrap();
+    assert!(csp.to_str().unwrap().contains("default-src 'self';"));
+    assert!(csp.to_str().unwrap().contains("form-action 'self';"));
+}
+
+#[test]
+fn sombrero_form_action_allow() {
+    let sombrero = Sombrero::new_empty().form_action_allow(&["https://pay.example.com"]);
+    let csp = sombrero.csp().unwrap().value("").unwrap();
+    assert_eq!(csp, "form-action 'self' https://pay.example.com;");
+}
+
+#[tokio::test]
+async fn sombrero_nosniff_on_all_content_types() {
+    let app = Router::new()
+        .route("/html", get(|| async { axum::response::Html("<p>hi</p>") }))
+        .route(
+            "/json",
+            get(|| async { ([("content-type", "application/json")], "{}") }),
+        )
+        .route("/binary", get(|| async { vec![0u8, 159, 146, 150] }))
+        .layer(Sombrero::default());
+    let server = test_server_router(app).await;
+    for path in ["html", "json", "binary"] {
+        let resp = reqwest::get(format!("{}{path}", server.url()))
+            .await
+            .unwrap();
+        assert_eq!(
+            resp.headers()["x-content-type-options"],
+            "nosniff",
+            "{path}"
+        );
+    }
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn csp_layer_extension_override_wins() {
+    let configured = ContentSecurityPolicy::new_empty().default_src(CspSource::SelfOrigin);
+    let override_csp = ContentSecurityPolicy::new_empty().default_src(CspSource::None);
+    let app = Router::new()
+        .route("/", get(test_handler))
+        .layer(CspLayer::new(configured))
+        .layer(Extension(override_csp));
+    let server = test_server_router(app).await;
+    let resp = reqwest::get(server.url()).await.unwrap();
+    assert_eq!(
+        resp.headers()["content-security-policy"],
+        "default-src 'none';"
+    );
+    server.shutdown().await;
+}
+
+#[cfg(feature = "minijinja")]
+#[test]
+fn minijinja_nonce_attr_filter() {
+    let mut env = minijinja::Environment::new();
+    env.add_filter("nonce_attr", crate::minijinja::filters::nonce_attr);
+    env.add_template(
+        "t.html",
+        "<script {{ nonce|nonce_attr }}>{{ nonce }}</script>",
+    )
+    .unwrap();
+    let nonce = crate::csp::CspNonce::new("n0nce");
+    let html = env
+        .get_template("t.html")
+        .unwrap()
+        .render(minijinja::context! { nonce => nonce.to_minijinja_value() })
+        .unwrap();
+    assert_eq!(html, r#"<script nonce="n0nce">n0nce</script>"#);
+}
+
+#[tokio::test]
+async fn sombrero_in_boxed_layer_stack() {
+    use std::convert::Infallible;
+
+    use tower::{util::BoxLayer, Layer, ServiceExt};
+
+    type Boxed = BoxLayer<
+        tower::util::BoxService<http::Request<String>, http::Response<String>, Infallible>,
+        http::Request<String>,
+        http::Response<String>,
+        Infallible,
+    >;
+    let layers: Vec<Boxed> = vec![
+        BoxLayer::new(Sombrero::new_empty().x_frame_options(crate::headers::XFrameOptions::Deny)),
+        BoxLayer::new(Sombrero::new_empty().referrer_policy(ReferrerPolicy::SameOrigin)),
+    ];
+    let inner = tower::service_fn(|_req: http::Request<String>| async {
+        Ok::<_, Infallible>(http::Response::new(String::new()))
+    })
+    .boxed();
+    let service = layers
+        .iter()
+        .fold(inner, |service, layer| layer.layer(service).boxed());
+    let resp = service
+        .oneshot(http::Request::new(String::new()))
+        .await
+        .unwrap();
+    assert_eq!(resp.headers()["x-frame-options"], "DENY");
+    assert_eq!(resp.headers()["referrer-policy"], "same-origin");
+}
+
+#[test]
+fn observatory_default_scores_well() {
+    let score = Sombrero::default().mozilla_observatory_score();
+    assert!(score.grade >= crate::observatory::Grade::B, "{score:?}");
+    // the default HSTS max-age of 180 days is just short of the observatory's six months
+    assert_eq!(score.points, 100, "{score:?}");
+}
+
+#[test]
+fn observatory_minimal_scores_poorly() {
+    use crate::observatory::{Grade, ObservatoryDeduction};
+
+    let score = Sombrero::new_empty().mozilla_observatory_score();
+    assert_eq!(score.points, 30);
+    assert_eq!(score.grade, Grade::D);
+    assert!(score
+        .deductions
+        .contains(&ObservatoryDeduction::CspNotImplemented));
+
+    let csp = ContentSecurityPolicy::strict_default()
+        .script_src([CspSource::SelfOrigin, CspSource::UnsafeInline]);
+    let score = Sombrero::default()
+        .content_security_policy(csp)
+        .mozilla_observatory_score();
+    assert_eq!(score.points, 70);
+    assert_eq!(score.grade, Grade::B);
+}
+
+#[tokio::test]
+async fn sombrero_helmet_compatible() {
+    let resp = apply_sombrero_oneshot(Sombrero::helmet_compatible(), http::Request::new(())).await;
+    let h = resp.headers();
+    assert_eq!(
+        h["content-security-policy"],
+        "default-src 'self';font-src 'self' https: data:;img-src 'self' data:;\
+         object-src 'none';script-src 'self';script-src-attr 'none';\
+         style-src 'self' https: 'unsafe-inline';base-uri 'self';form-action 'self';\
+         frame-ancestors 'self';upgrade-insecure-requests;"
+    );
+    assert_eq!(h["cross-origin-opener-policy"], "same-origin");
+    assert_eq!(h["cross-origin-resource-policy"], "same-origin");
+    assert_eq!(h["origin-agent-cluster"], "?1");
+    assert_eq!(h["referrer-policy"], "no-referrer");
+    assert_eq!(
+        h["strict-transport-security"],
+        "max-age=15552000;includeSubDomains"
+    );
+    assert_eq!(h["x-content-type-options"], "nosniff");
+    assert_eq!(h["x-dns-prefetch-control"], "off");
+    assert_eq!(h["x-download-options"], "noopen");
+    assert_eq!(h["x-frame-options"], "SAMEORIGIN");
+    assert_eq!(h["x-permitted-cross-domain-policies"], "none");
+    assert_eq!(h["x-xss-protection"], "0");
+}
+
+#[cfg(feature = "axum-routing")]
+#[tokio::test]
+async fn routed_sombrero_picks_config_by_route() {
+    use crate::{
+        headers::{CrossOriginEmbedderPolicy, XFrameOptions},
+        RoutedSombrero,
+    };
+
+    let routed = RoutedSombrero::new(Sombrero::default())
+        .route(
+            "/admin",
+            Sombrero::default()
+                .cross_origin_embedder_policy(CrossOriginEmbedderPolicy::RequireCorp),
+        )
+        .route(
+            "/public/{page}",
+            Sombrero::default()
+                .remove_content_security_policy()
+                .x_frame_options(XFrameOptions::Deny),
+        )
+        .build();
+    let app = Router::new()
+        .route("/", get(test_handler))
+        .route("/admin", get(test_handler))
+        .route("/public/{page}", get(test_handler))
+        .layer(routed);
+    let server = test_server_router(app).await;
+
+    let root = reqwest::get(server.url()).await.unwrap();
+    assert!(root.headers().get("cross-origin-embedder-policy").is_none());
+    assert!(root.headers().get("content-security-policy").is_some());
+
+    let admin = reqwest::get(format!("{}admin", server.url()))
+        .await
+        .unwrap();
+    assert_eq!(
+        admin.headers()["cross-origin-embedder-policy"],
+        "require-corp"
+    );
+
+    let public = reqwest::get(format!("{}public/about", server.url()))
+        .await
+        .unwrap();
+    assert!(public.headers().get("content-security-policy").is_none());
+    assert_eq!(public.headers()["x-frame-options"], "DENY");
+    server.shutdown().await;
+}
+
+#[test]
+fn detect_conflicts_csp_requires_https_without_hsts() {
+    use crate::SombreroConflict;
+
+    let sombrero = Sombrero::default();
+    assert!(sombrero
+        .content_security_policy
+        .as_ref()
+        .unwrap()
+        .requires_https());
+    assert!(sombrero.detect_conflicts().is_empty());
+    assert_eq!(
+        sombrero
+            .remove_strict_transport_security()
+            .detect_conflicts(),
+        [SombreroConflict::CspRequiresHttpsWithoutHsts]
+    );
+    assert!(Sombrero::new_empty().detect_conflicts().is_empty());
+}
+
+#[test]
+fn cors_compatible_corp() {
+    use crate::{headers::CrossOriginResourcePolicy, SombreroConflict};
+
+    let public = Sombrero::cors_compatible(true);
+    assert_eq!(
+        public.cross_origin_resource_policy,
+        Some(CrossOriginResourcePolicy::CrossOrigin)
+    );
+    let private = Sombrero::cors_compatible(false);
+    assert_eq!(
+        private.cross_origin_resource_policy,
+        Some(CrossOriginResourcePolicy::SameOrigin)
+    );
+
+    let mut any_origin = http::HeaderMap::new();
+    any_origin.insert("access-control-allow-origin", "*".parse().unwrap());
+    let mut one_origin = http::HeaderMap::new();
+    one_origin.insert(
+        "access-control-allow-origin",
+        "https://app.example.com".parse().unwrap(),
+    );
+    assert!(public.check_cors_compatibility(&any_origin).is_empty());
+    assert!(public.check_cors_compatibility(&one_origin).is_empty());
+    assert_eq!(
+        private.check_cors_compatibility(&any_origin),
+        [SombreroConflict::CorpContradictsCorsAnyOrigin]
+    );
+    assert!(private.check_cors_compatibility(&one_origin).is_empty());
+    assert!(private
+        .check_cors_compatibility(&http::HeaderMap::new())
+        .is_empty());
+    assert!(Sombrero::new_empty()
+        .check_cors_compatibility(&any_origin)
+        .is_empty());
+}
+
+#[tokio::test]
+async fn custom_csp_header_name() {
+    let sombrero = Sombrero::default()
+        .content_security_policy_report_only(ContentSecurityPolicy::strict_default())
+        .csp_header_name("x-content-security-policy")
+        .unwrap();
+    let response = apply_sombrero_oneshot(sombrero, http::Request::new(())).await;
+    let headers = response.headers();
+    assert!(headers.get("content-security-policy").is_none());
+    assert!(headers.get("x-content-security-policy").is_some());
+    assert!(headers.get("content-security-policy-report-only").is_some());
+
+    assert!(Sombrero::default().csp_header_name("not a header").is_err());
+    assert!(CspLayer::new(ContentSecurityPolicy::strict_default())
+        .with_header_name("bad\nname")
+        .is_err());
+}
+
+#[tokio::test]
+async fn with_reporting_matches_group_names() {
+    let sombrero = Sombrero::default().with_reporting("csp-violations", "https://example.com/r");
+    let response = apply_sombrero_oneshot(sombrero, http::Request::new(())).await;
+    let headers = response.headers();
+    let csp = headers["content-security-policy"].to_str().unwrap();
+    assert!(csp.ends_with("report-to csp-violations;"));
+    assert_eq!(
+        headers["reporting-endpoints"],
+        "csp-violations=\"https://example.com/r\""
+    );
+}
+
+#[test]
+fn header_budget_counts_bytes() {
+    let sombrero = Sombrero::new_empty()
+        .x_content_type_options(XContentTypeOptions)
+        .referrer_policy(ReferrerPolicy::NoReferrer)
+        .content_security_policy(ContentSecurityPolicy::new_empty().script_src([CspSource::Nonce]));
+    let nonce = "abc";
+    // "content-security-policy" + "script-src 'nonce-abc';"
+    let csp_len = 23 + 23;
+    // "x-content-type-options" + "nosniff"
+    let xcto_len = 22 + 7;
+    // "referrer-policy" + "no-referrer"
+    let referrer_len = 15 + 11;
+    assert_eq!(
+        sombrero.total_header_byte_length(nonce),
+        csp_len + xcto_len + referrer_len
+    );
+    assert_eq!(
+        sombrero.header_budget_report(nonce),
+        [
+            (http::header::CONTENT_SECURITY_POLICY, csp_len),
+            (http::header::X_CONTENT_TYPE_OPTIONS, xcto_len),
+            (http::header::REFERRER_POLICY, referrer_len),
+        ]
+    );
+    assert!(sombrero.exceeds_header_budget(nonce, 100));
+    assert!(!sombrero.exceeds_header_budget(nonce, 101));
+    assert_eq!(Sombrero::new_empty().total_header_byte_length(nonce), 0);
+}
+
+#[test]
+fn estimated_header_byte_length_matches_generated_nonces() {
+    let nonce = crate::random_string(crate::NONCE_LENGTH);
+    for sombrero in [
+        Sombrero::default(),
+        Sombrero::default()
+            .content_security_policy_report_only(ContentSecurityPolicy::strict_default())
+            .expose_csp_header(true),
+        Sombrero::new_empty(),
+    ] {
+        let mut static_headers = http::HeaderMap::new();
+        sombrero.insert_static_headers(&mut static_headers);
+        assert_eq!(
+            crate::estimated_header_byte_length(&sombrero, &static_headers),
+            sombrero.total_header_byte_length(&nonce)
+        );
+    }
+}
+
+#[tokio::test]
+async fn expose_csp_header_flag() {
+    let exposed = Sombrero::default().expose_csp_header(true);
+    let response = apply_sombrero_oneshot(exposed, http::Request::new(())).await;
+    assert_eq!(
+        response.headers()["access-control-expose-headers"],
+        "content-security-policy"
+    );
+
+    let proxied = Sombrero::default()
+        .csp_header_name("x-csp")
+        .unwrap()
+        .proxy_forwarding_mode(true);
+    let response = apply_sombrero_oneshot(proxied, http::Request::new(())).await;
+    assert_eq!(response.headers()["access-control-expose-headers"], "x-csp");
+
+    let response = apply_sombrero_oneshot(Sombrero::default(), http::Request::new(())).await;
+    assert!(response
+        .headers()
+        .get("access-control-expose-headers")
+        .is_none());
+}
+
+#[tokio::test]
+async fn nonce_is_generated_only_when_read() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    use crate::{csp::CspNonce, RANDOM_STRINGS_GENERATED};
+
+    let service = |read: bool| {
+        Sombrero::default().layer(service_fn(move |request: http::Request<()>| async move {
+            let nonce = request.extensions().get::<CspNonce>().unwrap();
+            if read {
+                assert_eq!(nonce.value().len(), crate::NONCE_LENGTH);
+            }
+            Ok::<_, std::convert::Infallible>(http::Response::new(()))
+        }))
+    };
+
+    // `tokio::test` runs on the current thread, so the counter sees every call.
+    let before = RANDOM_STRINGS_GENERATED.get();
+    let response = service(false)
+        .oneshot(http::Request::new(()))
+        .await
+        .unwrap();
+    assert!(response.headers().contains_key("content-security-policy"));
+    assert_eq!(RANDOM_STRINGS_GENERATED.get(), before);
+
+    service(true).oneshot(http::Request::new(())).await.unwrap();
+    assert_eq!(RANDOM_STRINGS_GENERATED.get(), before + 1);
+
+    let csp = ContentSecurityPolicy::new_empty().script_src([CspSource::Nonce]);
+    let sombrero = Sombrero::new_empty().content_security_policy(csp);
+    apply_sombrero_oneshot(sombrero, http::Request::new(())).await;
+    assert_eq!(RANDOM_STRINGS_GENERATED.get(), before + 2);
+}
+
+#[tokio::test]
+async fn service_worker_support_preset() {
+    let sombrero = Sombrero::default().with_service_worker_support();
+    let response = apply_sombrero_oneshot(sombrero.clone(), http::Request::new(())).await;
+    let csp = response.headers()["content-security-policy"]
+        .to_str()
+        .unwrap();
+    assert!(csp.contains("fetch-src 'self';"));
+    assert!(csp.contains("worker-src 'self';"));
+
+    let csp = sombrero
+        .with_service_worker_support()
+        .csp()
+        .unwrap()
+        .clone();
+    assert_eq!(csp.worker_src, [CspSource::SelfOrigin]);
+    assert_eq!(csp.fetch_src, [CspSource::SelfOrigin]);
+
+    let csp = Sombrero::new_empty()
+        .content_security_policy(
+            ContentSecurityPolicy::new_empty().worker_src([CspSchemeSource::Blob.into()]),
+        )
+        .with_service_worker_support()
+        .csp()
+        .unwrap()
+        .clone();
+    assert_eq!(
+        csp.worker_src,
+        [CspSchemeSource::Blob.into(), CspSource::SelfOrigin]
+    );
+}
+
+#[tokio::test]
+async fn cross_origin_resource_policy_by_path() {
+    use crate::headers::CrossOriginResourcePolicy;
+
+    let sombrero = Sombrero::default().cross_origin_resource_policy_when(
+        |path| path.starts_with("/assets/"),
+        CrossOriginResourcePolicy::CrossOrigin,
+    );
+    let asset = http::Request::get("/assets/font.woff2").body(()).unwrap();
+    let response = apply_sombrero_oneshot(sombrero.clone(), asset).await;
+    assert_eq!(
+        response.headers()["cross-origin-resource-policy"],
+        "cross-origin"
+    );
+
+    let page = http::Request::get("/index.html").body(()).unwrap();
+    let response = apply_sombrero_oneshot(sombrero, page).await;
+    assert_eq!(
+        response.headers()["cross-origin-resource-policy"],
+        "same-origin"
+    );
+}
+
+#[tokio::test]
+async fn modified_corp_wins_over_path_override() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    use crate::{headers::CrossOriginResourcePolicy, SombreroModifier};
+
+    let inner = service_fn(|request: http::Request<()>| async move {
+        let modifier = request.extensions().get::<SombreroModifier>().unwrap();
+        modifier.modify(|sombrero| {
+            sombrero.cross_origin_resource_policy(CrossOriginResourcePolicy::SameSite)
+        });
+        Ok::<_, std::convert::Infallible>(http::Response::new(()))
+    });
+    let sombrero = Sombrero::default().cross_origin_resource_policy_when(
+        |path| path.starts_with("/assets/"),
+        CrossOriginResourcePolicy::CrossOrigin,
+    );
+    let asset = http::Request::get("/assets/font.woff2").body(()).unwrap();
+    let response = sombrero.layer(inner).oneshot(asset).await.unwrap();
+    assert_eq!(
+        response.headers()["cross-origin-resource-policy"],
+        "same-site"
+    );
+}
+
+#[tokio::test]
+async fn nonce_cache_layer_appends_nonce_to_etag() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    use crate::csp::{CspNonce, NonceCacheLayer};
+
+    let service = NonceCacheLayer.layer(service_fn(|_: http::Request<()>| async {
+        let response = http::Response::builder()
+            .header("etag", "W/\"abc123\"")
+            .body(())
+            .unwrap();
+        Ok::<_, std::convert::Infallible>(response)
+    }));
+    let mut request = http::Request::new(());
+    request.extensions_mut().insert(CspNonce::new("xyz789"));
+    let response = service.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.headers()["etag"], "W/\"abc123-nonce-xyz789\"");
+
+    let response = service.oneshot(http::Request::new(())).await.unwrap();
+    assert_eq!(response.headers()["etag"], "W/\"abc123\"");
+}
+
+#[tokio::test]
+async fn no_cache_with_nonce_sets_cache_control() {
+    let csp = ContentSecurityPolicy::new_empty().script_src([CspSource::Nonce]);
+    let sombrero = Sombrero::new_empty()
+        .content_security_policy(csp)
+        .no_cache_with_nonce();
+    let response = apply_sombrero_oneshot(sombrero, http::Request::new(())).await;
+    assert_eq!(response.headers()["cache-control"], "no-store");
+
+    let sombrero = Sombrero::default().no_cache_with_nonce();
+    let response = apply_sombrero_oneshot(sombrero, http::Request::new(())).await;
+    assert!(response.headers().get("cache-control").is_none());
+}
+
+#[tokio::test]
+async fn graphql_presets() {
+    let response = apply_sombrero_oneshot(Sombrero::for_graphql(), http::Request::new(())).await;
+    let headers = response.headers();
+    assert!(headers.get("content-security-policy").is_none());
+    assert_eq!(headers["cross-origin-resource-policy"], "same-origin");
+    assert_eq!(headers["referrer-policy"], "no-referrer");
+    assert_eq!(headers["x-content-type-options"], "nosniff");
+    assert_eq!(headers["x-frame-options"], "DENY");
+    assert!(headers.contains_key("strict-transport-security"));
+
+    let playground = Sombrero::for_graphql_playground();
+    let response = apply_sombrero_oneshot(playground, http::Request::new(())).await;
+    let headers = response.headers();
+    let csp = headers["content-security-policy"].to_str().unwrap();
+    assert!(csp.contains("script-src 'self' 'unsafe-inline' https://cdn.jsdelivr.net;"));
+    assert!(csp.contains("font-src 'self' https://fonts.gstatic.com;"));
+    assert_eq!(headers["x-frame-options"], "DENY");
+}
+
+#[tokio::test]
+async fn repeated_requests_get_identical_headers() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    let sombrero = Sombrero::default().with_reporting("csp", "https://example.com/r");
+    let service = sombrero.layer(service_fn(|_: http::Request<()>| async {
+        Ok::<_, std::convert::Infallible>(http::Response::new(()))
+    }));
+    let first = service
+        .clone()
+        .oneshot(http::Request::new(()))
+        .await
+        .unwrap();
+    let second = service.oneshot(http::Request::new(())).await.unwrap();
+    assert_eq!(first.headers(), second.headers());
+
+    let mut expected = http::HeaderMap::new();
+    sombrero.insert_headers(&mut expected, "");
+    assert_eq!(first.headers(), &expected);
+}
+
+#[tokio::test]
+async fn strip_for_http10_clients() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    use crate::{csp::CspNonce, SombreroModifier};
+
+    let sombrero = Sombrero::default().strip_for_http10_clients(true);
+    let request = http::Request::builder()
+        .version(http::Version::HTTP_10)
+        .body(())
+        .unwrap();
+    let response = apply_sombrero_oneshot(sombrero.clone(), request).await;
+    assert!(response.headers().is_empty());
+
+    // handlers still get the extensions, only the headers are skipped
+    let inner = service_fn(|request: http::Request<()>| async move {
+        let nonce = request.extensions().get::<CspNonce>().unwrap();
+        assert!(request.extensions().get::<SombreroModifier>().is_some());
+        let response = http::Response::builder()
+            .header("x-nonce", nonce.as_str())
+            .body(())
+            .unwrap();
+        Ok::<_, std::convert::Infallible>(response)
+    });
+    let request = http::Request::builder()
+        .version(http::Version::HTTP_10)
+        .body(())
+        .unwrap();
+    let response = sombrero
+        .clone()
+        .layer(inner)
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.headers().len(), 1);
+    assert!(response.headers().contains_key("x-nonce"));
+
+    for version in [http::Version::HTTP_11, http::Version::HTTP_2] {
+        let request = http::Request::builder().version(version).body(()).unwrap();
+        let response = apply_sombrero_oneshot(sombrero.clone(), request).await;
+        assert!(response.headers().contains_key("content-security-policy"));
+        assert!(response.headers().contains_key("x-frame-options"));
+    }
+}
+
+#[tokio::test]
+async fn frame_protection_keeps_headers_consistent() {
+    use crate::FrameProtection;
+
+    let cases = [
+        (
+            FrameProtection::DenyAll,
+            Some("DENY"),
+            "frame-ancestors 'none';",
+        ),
+        (
+            FrameProtection::SameOrigin,
+            Some("SAMEORIGIN"),
+            "frame-ancestors 'self';",
+        ),
+        (
+            FrameProtection::AllowOrigins(vec!["https://partner.example".to_string()]),
+            None,
+            "frame-ancestors https://partner.example;",
+        ),
+    ];
+    for (protection, xfo, frame_ancestors) in cases {
+        let sombrero = Sombrero::default().frame_protection(protection);
+        let response = apply_sombrero_oneshot(sombrero, http::Request::new(())).await;
+        let headers = response.headers();
+        assert_eq!(
+            headers.get("x-frame-options").map(|v| v.to_str().unwrap()),
+            xfo
+        );
+        let csp = headers["content-security-policy"].to_str().unwrap();
+        assert!(csp.contains(frame_ancestors), "{csp}");
+    }
+}
+
+fn arb_csp_source() -> impl proptest::strategy::Strategy<Value = CspSource> {
+    use proptest::prelude::*;
+
+    prop_oneof![
+        "[a-z]{1,10}(\\.[a-z]{2,5}){0,2}".prop_map(|host| CspSource::Host(host.into())),
+        Just(CspSource::Wildcard),
+        prop_oneof![
+            Just(CspSchemeSource::Data),
+            Just(CspSchemeSource::Mediastream),
+            Just(CspSchemeSource::Blob),
+            Just(CspSchemeSource::Filesystem),
+            Just(CspSchemeSource::Http),
+            Just(CspSchemeSource::Https),
+            Just(CspSchemeSource::Ws),
+            Just(CspSchemeSource::Wss),
+        ]
+        .prop_map(CspSource::Scheme),
+        Just(CspSource::Nonce),
+        "[A-Za-z0-9+/]{43}=".prop_map(|digest| CspSource::Hash(CspHashAlgorithm::Sha256, digest)),
+        Just(CspSource::SelfOrigin),
+        Just(CspSource::UnsafeEval),
+        Just(CspSource::WasmUnsafeEval),
+        Just(CspSource::UnsafeHashes),
+        Just(CspSource::UnsafeInline),
+        Just(CspSource::StrictDynamic),
+        Just(CspSource::ReportSample),
+        Just(CspSource::InlineSpeculationRules),
+        Just(CspSource::None),
+    ]
+}
+
+fn arb_trusted_types_source() -> impl proptest::strategy::Strategy<Value = CspSource> {
+    use proptest::prelude::*;
+
+    prop_oneof![
+        "[a-z][a-z0-9-]{0,10}".prop_map(CspSource::TrustedTypePolicy),
+        Just(CspSource::TrustedTypeStar),
+        Just(CspSource::TrustedTypeNone),
+        Just(CspSource::AllowDuplicates),
+    ]
+}
+
+fn arb_csp() -> impl proptest::strategy::Strategy<Value = ContentSecurityPolicy> {
+    use proptest::{collection::vec, option, prelude::*};
+
+    (
+        vec(vec(arb_csp_source(), 0..4), 22),
+        vec(arb_trusted_types_source(), 0..3),
+        any::<bool>(),
+        option::of("[a-z][a-z0-9-]{0,10}"),
+        option::of("(https://[a-z]{1,8}\\.example)?/[a-z]{0,8}"),
+    )
+        .prop_map(
+            |(mut lists, trusted_types, upgrade, report_to, report_uri)| {
+                let mut next = || lists.pop().unwrap();
+                ContentSecurityPolicy {
+                    default_src: next(),
+                    child_src: next(),
+                    connect_src: next(),
+                    fetch_src: next(),
+                    font_src: next(),
+                    frame_src: next(),
+                    fenced_frame_src: next(),
+                    img_src: next(),
+                    manifest_src: next(),
+                    media_src: next(),
+                    object_src: next(),
+                    script_src: next(),
+                    script_src_elem: next(),
+                    script_src_attr: next(),
+                    style_src: next(),
+                    style_src_elem: next(),
+                    style_src_attr: next(),
+                    worker_src: next(),
+                    base_uri: next(),
+                    sandbox: next(),
+                    form_action: next(),
+                    frame_ancestors: next(),
+                    trusted_types,
+                    upgrade_insecure_requests: upgrade,
+                    report_to,
+                    report_uri,
+                    normalize_source_order: false,
+                }
+            },
+        )
+}
+
+proptest::proptest! {
+    #[test]
+    fn csp_parse_round_trips(csp in arb_csp()) {
+        let value = csp.value("n0nce").unwrap();
+        let parsed = ContentSecurityPolicy::parse(value.to_str().unwrap()).unwrap();
+        proptest::prop_assert!(parsed.semantically_eq(&csp), "{value:?} parsed as {parsed:?}");
+    }
+
+    #[test]
+    fn csp_estimated_byte_length_is_exact(csp in arb_csp()) {
+        let value = csp.value("n0nce").unwrap();
+        proptest::prop_assert_eq!(csp.estimated_byte_length("n0nce"), value.len());
+    }
+}
+
+#[test]
+fn csp_value_fast_path() {
+    let empty = ContentSecurityPolicy::new_empty();
+    assert_eq!(empty.value("abc").unwrap(), "");
+    assert_eq!(empty.estimated_byte_length("abc"), 0);
+    let upgrade = empty.upgrade_insecure_requests(true);
+    assert_eq!(upgrade.value("abc").unwrap(), "upgrade-insecure-requests;");
+    let report = upgrade.report_to("csp");
+    assert_eq!(
+        report.value("abc").unwrap(),
+        "upgrade-insecure-requests;report-to csp;"
+    );
+}
+
+#[tokio::test]
+async fn server_sent_events_stream_without_buffering() {
+    use std::{sync::Arc, time::Duration};
+
+    use axum::{body::Body, response::IntoResponse};
+    use tokio::sync::Notify;
+
+    let finish = Arc::new(Notify::new());
+    let handler_finish = finish.clone();
+    let handler = move || async move {
+        let chunk = async move {
+            handler_finish.notified().await;
+            Ok::<_, std::convert::Infallible>("data: done\n\n")
+        };
+        (
+            [("content-type", "text/event-stream")],
+            Body::from_stream(futures_util::stream::once(chunk)),
+        )
+            .into_response()
+    };
+    let app = Router::new()
+        .route("/events", get(handler))
+        .layer(Sombrero::default().for_server_sent_events());
+    let server = test_server_router(app).await;
+
+    let response = tokio::time::timeout(
+        Duration::from_secs(5),
+        reqwest::get(format!("{}events", server.url())),
+    )
+    .await
+    .expect("headers should arrive before the body is finished")
+    .unwrap();
+    let headers = response.headers();
+    assert!(headers.get("content-security-policy").is_none());
+    assert_eq!(headers["cache-control"], "no-store");
+    assert_eq!(headers["x-content-type-options"], "nosniff");
+    assert_eq!(headers["cross-origin-resource-policy"], "same-origin");
+    assert_eq!(headers["content-type"], "text/event-stream");
+
+    finish.notify_one();
+    assert_eq!(response.text().await.unwrap(), "data: done\n\n");
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn sequential_nonce_generator() {
+    use crate::csp::SequentialNonce;
+
+    let csp = ContentSecurityPolicy::new_empty().script_src([CspSource::Nonce]);
+    let sombrero = Sombrero::new_empty()
+        .content_security_policy(csp)
+        .nonce_generator(SequentialNonce::new("test"));
+    let first = apply_sombrero_oneshot(sombrero.clone(), http::Request::new(())).await;
+    let second = apply_sombrero_oneshot(sombrero, http::Request::new(())).await;
+    assert_eq!(
+        first.headers()["content-security-policy"],
+        "script-src 'nonce-test0';"
+    );
+    assert_eq!(
+        second.headers()["content-security-policy"],
+        "script-src 'nonce-test1';"
+    );
+}
+
+#[tokio::test]
+async fn modifier_nonce_matches_header() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    use crate::SombreroModifier;
+
+    let inner = service_fn(|request: http::Request<()>| async move {
+        let modifier = request.extensions().get::<SombreroModifier>().unwrap();
+        modifier.modify(|sombrero| {
+            sombrero.content_security_policy(
+                ContentSecurityPolicy::new_empty().script_src([CspSource::Nonce]),
+            )
+        });
+        let response = http::Response::builder()
+            .header("x-nonce", modifier.nonce().as_str())
+            .body(())
+            .unwrap();
+        Ok::<_, std::convert::Infallible>(response)
+    });
+    let response = Sombrero::default()
+        .layer(inner)
+        .oneshot(http::Request::new(()))
+        .await
+        .unwrap();
+    let headers = response.headers();
+    assert_eq!(
+        headers["content-security-policy"],
+        format!(
+            "script-src 'nonce-{}';",
+            headers["x-nonce"].to_str().unwrap()
+        )
+    );
+}
+
+#[tokio::test]
+async fn modifier_nonce_uses_nonce_generator() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    use crate::{csp::SequentialNonce, SombreroModifier};
+
+    let inner = service_fn(|request: http::Request<()>| async move {
+        let modifier = request.extensions().get::<SombreroModifier>().unwrap();
+        modifier.modify(|sombrero| {
+            sombrero.content_security_policy(
+                ContentSecurityPolicy::new_empty().script_src([CspSource::Nonce]),
+            )
+        });
+        Ok::<_, std::convert::Infallible>(http::Response::new(()))
+    });
+    let sombrero = Sombrero::new_empty().nonce_generator(SequentialNonce::new("seq"));
+    let response = sombrero
+        .layer(inner)
+        .oneshot(http::Request::new(()))
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers()["content-security-policy"],
+        "script-src 'nonce-seq0';"
+    );
+}
+
+#[cfg(feature = "axum")]
+#[tokio::test]
+async fn handler_modifies_sombrero() {
+    use crate::SombreroModifier;
+
+    async fn pdf(modifier: SombreroModifier) -> &'static str {
+        modifier.modify(|sombrero| {
+            sombrero
+                .remove_x_frame_options()
+                .edit_csp(|csp| csp.frame_ancestors([CspSource::Wildcard]))
+        });
+        "%PDF-1.7"
+    }
+
+    let app = Router::new()
+        .route("/", get(test_handler))
+        .route("/doc.pdf", get(pdf))
+        .layer(Sombrero::default());
+    let server = test_server_router(app).await;
+
+    let pdf = reqwest::get(format!("{}doc.pdf", server.url()))
+        .await
+        .unwrap();
+    assert!(pdf.headers().get("x-frame-options").is_none());
+    let csp = pdf.headers()["content-security-policy"].to_str().unwrap();
+    assert!(csp.contains("frame-ancestors *;"));
+
+    let page = reqwest::get(server.url()).await.unwrap();
+    assert_eq!(page.headers()["x-frame-options"], "SAMEORIGIN");
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn recommended_for_cookies_by_samesite() {
+    use crate::SameSite;
+
+    let cases = [
+        (SameSite::Strict, "same-origin", Some("require-corp")),
+        (SameSite::Lax, "same-site", None),
+        (SameSite::None, "cross-origin", Some("unsafe-none")),
+    ];
+    for (samesite, corp, coep) in cases {
+        let sombrero = Sombrero::recommended_for_cookies(samesite);
+        let response = apply_sombrero_oneshot(sombrero, http::Request::new(())).await;
+        let headers = response.headers();
+        assert_eq!(headers["cross-origin-resource-policy"], corp);
+        assert_eq!(
+            headers
+                .get("cross-origin-embedder-policy")
+                .map(|v| v.to_str().unwrap()),
+            coep
+        );
+        assert!(headers.contains_key("content-security-policy"));
+    }
+}
+
+#[tokio::test]
+async fn stacked_csp_layer_and_sombrero() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    let inner_csp = ContentSecurityPolicy::new_empty().default_src([CspSource::None]);
+    let stack = |sombrero: Sombrero| {
+        sombrero.layer(CspLayer::new(inner_csp.clone()).layer(service_fn(
+            |_: http::Request<()>| async {
+                Ok::<_, std::convert::Infallible>(http::Response::new(()))
+            },
+        )))
+    };
+
+    // by default, the outer `Sombrero` replaces the inner policy
+    let response = stack(Sombrero::default())
+        .oneshot(http::Request::new(()))
+        .await
+        .unwrap();
+    let values: Vec<_> = response
+        .headers()
+        .get_all("content-security-policy")
+        .iter()
+        .collect();
+    assert_eq!(values.len(), 1);
+    assert_ne!(values[0], "default-src 'none';");
+
+    let response = stack(Sombrero::default().respect_existing_csp(true))
+        .oneshot(http::Request::new(()))
+        .await
+        .unwrap();
+    let values: Vec<_> = response
+        .headers()
+        .get_all("content-security-policy")
+        .iter()
+        .collect();
+    assert_eq!(values, ["default-src 'none';"]);
+}
+
+#[tokio::test]
+async fn strip_downstream_csp_keeps_report_only() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    let service = |sombrero: Sombrero| {
+        sombrero.layer(service_fn(|_: http::Request<()>| async {
+            let response = http::Response::builder()
+                .header("content-security-policy", "default-src 'none';")
+                .body(())
+                .unwrap();
+            Ok::<_, std::convert::Infallible>(response)
+        }))
+    };
+    let monitor = Sombrero::default()
+        .remove_content_security_policy()
+        .content_security_policy_report_only(ContentSecurityPolicy::strict_default())
+        .strip_downstream_csp(true);
+
+    let response = service(monitor.clone())
+        .oneshot(http::Request::new(()))
+        .await
+        .unwrap();
+    let headers = response.headers();
+    assert!(!headers.contains_key("content-security-policy"));
+    assert!(headers["content-security-policy-report-only"]
+        .to_str()
+        .unwrap()
+        .starts_with("default-src 'self';"));
+
+    // stripping also applies in missing-only mode, so nothing downstream is kept
+    let response = service(monitor.clone().missing_only_mode())
+        .oneshot(http::Request::new(()))
+        .await
+        .unwrap();
+    assert!(!response.headers().contains_key("content-security-policy"));
+
+    let response = service(monitor.strip_downstream_csp(false))
+        .oneshot(http::Request::new(()))
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers()["content-security-policy"],
+        "default-src 'none';"
+    );
 }
 
 #[tokio::test]
-async fn sombrero_layer_changes_csp_nonce() {
-    let csp = ContentSecurityPolicy::new_empty().script_src(CspSource::Nonce);
+async fn missing_only_mode_keeps_existing_headers() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    let service = |sombrero: Sombrero| {
+        sombrero.layer(service_fn(|_: http::Request<()>| async {
+            let response = http::Response::builder()
+                .header("x-frame-options", "DENY")
+                .header("content-security-policy", "default-src 'none';")
+                .body(())
+                .unwrap();
+            Ok::<_, std::convert::Infallible>(response)
+        }))
+    };
+
+    let response = service(Sombrero::default())
+        .oneshot(http::Request::new(()))
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-frame-options"], "SAMEORIGIN");
+
+    let response = service(Sombrero::default().missing_only_mode())
+        .oneshot(http::Request::new(()))
+        .await
+        .unwrap();
+    let headers = response.headers();
+    assert_eq!(headers["x-frame-options"], "DENY");
+    assert_eq!(headers.get_all("content-security-policy").iter().count(), 1);
+    assert_eq!(headers["content-security-policy"], "default-src 'none';");
+    assert_eq!(headers["x-content-type-options"], "nosniff");
+    assert!(headers.contains_key("strict-transport-security"));
+}
+
+#[test]
+fn apply_missing_only_adds_only_missing_headers() {
+    let mut response = http::Response::builder()
+        .header("referrer-policy", "origin")
+        .body(())
+        .unwrap();
+    Sombrero::default().apply_missing_only(&mut response, "nonce");
+    let headers = response.headers();
+    assert_eq!(headers.get_all("referrer-policy").iter().count(), 1);
+    assert_eq!(headers["referrer-policy"], "origin");
+    assert_eq!(headers["x-frame-options"], "SAMEORIGIN");
+    assert!(headers.contains_key("content-security-policy"));
+
+    let mut replaced = http::Response::builder()
+        .header("referrer-policy", "origin")
+        .body(())
+        .unwrap();
+    Sombrero::default().apply_to_response_ref(&mut replaced, "nonce");
+    assert_eq!(replaced.headers()["referrer-policy"], "no-referrer");
+}
+
+#[test]
+fn enforce_secure_cookies_with_sts() {
+    use crate::headers::StrictTransportSecurity;
+
+    let added = Sombrero::new_empty().enforce_secure_cookies_with_sts(true);
+    assert_eq!(
+        added.strict_transport_security,
+        Some(StrictTransportSecurity::DEFAULT)
+    );
+    let custom = StrictTransportSecurity::DEFAULT.max_age_secs(60);
+    let kept = Sombrero::new_empty()
+        .strict_transport_security(custom)
+        .enforce_secure_cookies_with_sts(true);
+    assert_eq!(kept.strict_transport_security, Some(custom));
+    let disabled = Sombrero::new_empty().enforce_secure_cookies_with_sts(false);
+    assert_eq!(disabled.strict_transport_security, None);
+}
+
+#[cfg(feature = "axum-extra")]
+#[tokio::test]
+async fn secure_cookie_layer_adds_sts() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    use crate::SecureCookieSombreroLayer;
+
+    let service = SecureCookieSombreroLayer::new(Sombrero::new_empty()).layer(service_fn(
+        |_: http::Request<()>| async {
+            let response = http::Response::builder()
+                .header("set-cookie", "session=abc; Secure")
+                .body(())
+                .unwrap();
+            Ok::<_, std::convert::Infallible>(response)
+        },
+    ));
+    let request = http::Request::get("/")
+        .header("x-forwarded-proto", "http")
+        .body(())
+        .unwrap();
+    let response = service.oneshot(request).await.unwrap();
+    assert!(response.headers().contains_key("strict-transport-security"));
+    assert_eq!(response.headers()["set-cookie"], "session=abc; Secure");
+}
+
+#[test]
+fn apply_to_parts_and_response_ref() {
+    let csp = ContentSecurityPolicy::new_empty().script_src([CspSource::Nonce]);
+    let sombrero = Sombrero::new_empty()
+        .content_security_policy(csp)
+        .x_content_type_options(XContentTypeOptions);
+
+    let (mut parts, body) = http::Response::new("body").into_parts();
+    sombrero.apply_to_parts(&mut parts, "abc");
+    assert_eq!(
+        parts.headers["content-security-policy"],
+        "script-src 'nonce-abc';"
+    );
+    assert_eq!(parts.headers["x-content-type-options"], "nosniff");
+    assert_eq!(parts.headers.len(), 2);
+
+    let mut response = http::Response::from_parts(parts, body);
+    response.headers_mut().clear();
+    sombrero.apply_to_response_ref(&mut response, "xyz");
+    assert_eq!(
+        response.headers()["content-security-policy"],
+        "script-src 'nonce-xyz';"
+    );
+    assert_eq!(*response.body(), "body");
+}
+
+#[tokio::test]
+async fn strict_permissions_policy() {
+    let sombrero = Sombrero::default().with_strict_permissions_policy();
+    let response = apply_sombrero_oneshot(sombrero, http::Request::new(())).await;
+    let policy = response.headers()["permissions-policy"].to_str().unwrap();
+    for feature in ["camera", "microphone", "geolocation", "usb", "payment"] {
+        assert!(policy.contains(&format!("{feature}=()")), "{policy}");
+    }
+}
+
+#[test]
+fn append_helper_keeps_existing_vary() {
+    struct Vary;
+    impl crate::headers::Header for Vary {
+        fn name(&self) -> http::HeaderName {
+            http::header::VARY
+        }
+
+        fn value(&self) -> http::HeaderValue {
+            http::HeaderValue::from_static("Accept-Encoding")
+        }
+    }
+
+    let mut headers = http::HeaderMap::new();
+    headers.insert(http::header::VARY, "Origin".parse().unwrap());
+    crate::add_opt_header_append(&mut headers, Some(Vary));
+    let vary: Vec<_> = headers.get_all(http::header::VARY).iter().collect();
+    assert_eq!(vary, ["Origin", "Accept-Encoding"]);
+    assert!(crate::is_list_header(&http::header::VARY));
+    assert!(!crate::is_list_header(&http::header::X_FRAME_OPTIONS));
+}
+
+#[tokio::test]
+async fn list_headers_append_and_single_headers_replace() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    let sombrero = Sombrero::default()
+        .x_frame_options(crate::headers::XFrameOptions::Deny)
+        .with_reporting("csp", "https://example.com/reports");
+    let service = sombrero.layer(service_fn(|_: http::Request<()>| async {
+        let response = http::Response::builder()
+            .header("vary", "Origin")
+            .header("reporting-endpoints", "app=\"https://example.com/app\"")
+            .header("x-frame-options", "SAMEORIGIN")
+            .body(())
+            .unwrap();
+        Ok::<_, std::convert::Infallible>(response)
+    }));
+    let response = service.oneshot(http::Request::new(())).await.unwrap();
+    let headers = response.headers();
+
+    let vary: Vec<_> = headers.get_all("vary").iter().collect();
+    assert_eq!(vary, ["Origin"]);
+    let endpoints: Vec<_> = headers.get_all("reporting-endpoints").iter().collect();
+    assert_eq!(
+        endpoints,
+        [
+            "app=\"https://example.com/app\"",
+            "csp=\"https://example.com/reports\""
+        ]
+    );
+    let frame_options: Vec<_> = headers.get_all("x-frame-options").iter().collect();
+    assert_eq!(frame_options, ["DENY"]);
+}
+
+#[test]
+fn security_scanner_missing_headers() {
+    assert_eq!(
+        Sombrero::default().missing_headers(),
+        ["Permissions-Policy"]
+    );
+    assert!(Sombrero::default()
+        .with_strict_permissions_policy()
+        .missing_headers()
+        .is_empty());
+    assert_eq!(Sombrero::new_empty().missing_headers().len(), 6);
+}
+
+#[test]
+fn security_scanner_json() {
+    let json = Sombrero::new_empty()
+        .x_frame_options(crate::headers::XFrameOptions::Deny)
+        .referrer_policy(ReferrerPolicy::NoReferrer)
+        .to_security_scanner_json("");
+    assert_eq!(
+        json,
+        "{\"headers\":{\"referrer-policy\":\"no-referrer\",\"x-frame-options\":\"DENY\"},\
+         \"missing\":[\"Content-Security-Policy\",\"Strict-Transport-Security\",\
+         \"X-Content-Type-Options\",\"Permissions-Policy\"],\"score\":33}"
+    );
+
+    let json = Sombrero::default().to_security_scanner_json("abc");
+    assert!(json.contains("\"score\":83}"), "{json}");
+}
+
+#[test]
+fn sombrero_from_csp_keeps_other_defaults() {
+    // `Debug` covers every field, so matching output means nothing but the CSP changed
+    let same = |a: Sombrero, b: Sombrero| assert_eq!(format!("{a:?}"), format!("{b:?}"));
+    let csp = ContentSecurityPolicy::locked_down();
+    let report_only = ContentSecurityPolicy::strict_default();
+
+    let sombrero = Sombrero::from(csp.clone());
+    assert_eq!(sombrero.content_security_policy.as_deref(), Some(&csp));
+    same(
+        sombrero,
+        Sombrero::default().content_security_policy(csp.clone()),
+    );
+
+    same(
+        Sombrero::from(Some(csp.clone())),
+        Sombrero::default().content_security_policy(csp.clone()),
+    );
+    let sombrero = Sombrero::from(None::<ContentSecurityPolicy>);
+    assert!(sombrero.content_security_policy.is_none());
+    same(
+        sombrero,
+        Sombrero::default().remove_content_security_policy(),
+    );
+
+    let sombrero = Sombrero::from((csp.clone(), report_only.clone()));
+    assert_eq!(
+        sombrero.content_security_policy_report_only.as_deref(),
+        Some(&report_only)
+    );
+    same(
+        sombrero,
+        Sombrero::default()
+            .content_security_policy(csp)
+            .content_security_policy_report_only(report_only),
+    );
+}
+
+#[test]
+fn missing_recommended_headers() {
+    assert_eq!(Sombrero::new_empty().missing_recommended().len(), 8);
+    assert_eq!(
+        Sombrero::default().missing_recommended(),
+        ["Permissions-Policy"]
+    );
+
+    // frame-ancestors stands in for X-Frame-Options
+    let missing = Sombrero::default()
+        .remove_x_frame_options()
+        .edit_csp(|csp| ContentSecurityPolicy {
+            frame_ancestors: vec![],
+            ..csp
+        })
+        .missing_recommended();
+    assert!(missing.contains(&"X-Frame-Options"));
+    let missing = Sombrero::default()
+        .remove_x_frame_options()
+        .edit_csp(|csp| csp.frame_ancestors([CspSource::None]))
+        .missing_recommended();
+    assert!(!missing.contains(&"X-Frame-Options"));
+}
+
+#[test]
+fn csp_nonce_helpers() {
+    let nonce = crate::csp::CspNonce::new("Zm9vYmFy");
+    assert_eq!(nonce.value(), "Zm9vYmFy");
+    assert_eq!(nonce.as_str(), "Zm9vYmFy");
+    assert_eq!(nonce.as_ref(), "Zm9vYmFy");
+    assert_eq!(nonce.script_attr(), r#"nonce="Zm9vYmFy""#);
+    assert_eq!(nonce.style_attr(), r#"nonce="Zm9vYmFy""#);
+    assert_eq!(
+        format!("<style {}></style>", nonce.style_attr()),
+        r#"<style nonce="Zm9vYmFy"></style>"#
+    );
+}
+
+#[tokio::test]
+async fn timed_nonce_rotation_layer_starts_from_request_nonce() {
+    use std::time::Duration;
+
+    use tower::{service_fn, Layer, ServiceExt};
+
+    use crate::csp::{CspNonce, RotatingNonce, TimedNonceRotationLayer};
+
+    let csp = ContentSecurityPolicy::new_empty().script_src([CspSource::Nonce]);
+    let inner = service_fn(|request: http::Request<()>| async move {
+        let nonce = request.extensions().get::<CspNonce>().unwrap().clone();
+        let rotating = request.extensions().get::<RotatingNonce>().unwrap();
+        assert_eq!(rotating.current().as_str(), nonce.as_str());
+        assert_eq!(rotating.sse_event(), None);
+        Ok::<_, std::convert::Infallible>(http::Response::new(()))
+    });
+    let service = Sombrero::new_empty()
+        .content_security_policy(csp)
+        .layer(TimedNonceRotationLayer::new(Duration::from_secs(60)).layer(inner));
+    service.oneshot(http::Request::new(())).await.unwrap();
+}
+
+#[cfg(feature = "axum")]
+#[tokio::test]
+async fn csp_nonce_extractor_matches_header() {
+    use crate::csp::CspNonce;
+
+    async fn nonce_handler(nonce: CspNonce) -> String {
+        nonce.to_string()
+    }
+
+    let csp = ContentSecurityPolicy::new_empty().script_src([CspSource::Nonce]);
     let sombrero = Sombrero::new_empty().content_security_policy(csp);
-    let server = test_server(sombrero).await;
-    let resp1 = reqwest::get(server.url()).await.unwrap();
-    let resp2 = reqwest::get(server.url()).await.unwrap();
-    let nonce1 = helper_get_nonce(&resp1, "content-security-policy");
-    let nonce2 = helper_get_nonce(&resp2, "content-security-policy");
-    assert_ne!(nonce1, nonce2);
+    let app = Router::new().route("/", get(nonce_handler)).layer(sombrero);
+    let server = test_server_router(app).await;
+    let response = reqwest::get(server.url())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let header = response.headers()["content-security-policy"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let nonce = response.text().await.unwrap();
     server.shutdown().await;
+
+    assert!(!nonce.is_empty());
+    assert_eq!(header, format!("script-src 'nonce-{nonce}';"));
+}
+
+#[test]
+fn sombrero_strictness_ordering() {
+    assert!(Sombrero::new_empty() < Sombrero::default());
+    assert!(Sombrero::default() > Sombrero::new_empty());
+    assert_eq!(
+        Sombrero::default().partial_cmp(&Sombrero::default()),
+        Some(std::cmp::Ordering::Equal)
+    );
+
+    let fewer_headers = Sombrero::default().remove_x_frame_options();
+    assert_eq!(
+        Sombrero::default().partial_cmp(&fewer_headers),
+        Some(std::cmp::Ordering::Greater)
+    );
+    assert!(fewer_headers < Sombrero::default());
+
+    let stricter = Sombrero::default()
+        .x_frame_options(crate::headers::XFrameOptions::Deny)
+        .edit_csp(|csp| {
+            csp.upgrade_insecure_requests(true)
+                .script_src(CspSource::None)
+        });
+    assert!(Sombrero::default() < stricter);
+
+    // each allows something the other doesn't
+    let looser_frames = Sombrero::default().remove_x_frame_options();
+    let looser_referrer = Sombrero::default().referrer_policy(ReferrerPolicy::UnsafeUrl);
+    assert_eq!(looser_frames.partial_cmp(&looser_referrer), None);
+
+    let no_store = Sombrero::default().no_store(true);
+    assert_ne!(Sombrero::default(), no_store);
+    assert!(Sombrero::default() < no_store);
+    let exposed = Sombrero::default().expose_csp_header(true);
+    assert_eq!(Sombrero::default().partial_cmp(&exposed), None);
+}
+
+#[test]
+fn csp_strictly_more_permissive() {
+    let strict = ContentSecurityPolicy::strict_default();
+    let looser = strict.clone().script_src([
+        CspSource::SelfOrigin,
+        CspSource::host_static("https://cdn.example.com"),
+    ]);
+    assert!(looser.is_strictly_more_permissive_than(&strict));
+    assert!(!strict.is_strictly_more_permissive_than(&looser));
+    assert!(!strict.is_strictly_more_permissive_than(&strict));
+    // an unset directive allows anything
+    assert!(ContentSecurityPolicy::new_empty().is_strictly_more_permissive_than(&strict));
+    // spelling out the default-src fallback changes nothing
+    let explicit = strict
+        .clone()
+        .font_src(strict.font_src.clone())
+        .connect_src([CspSource::SelfOrigin]);
+    assert!(!explicit.is_strictly_more_permissive_than(&strict));
+    assert!(!strict.is_strictly_more_permissive_than(&explicit));
+}
+
+crate::assert_fixture!(standard_fixtures);
+
+crate::assert_fixture!(
+    single_header_fixtures,
+    crate::testing::SombreroFixture::default()
+        .case(
+            Sombrero::new_empty().x_content_type_options(XContentTypeOptions),
+            [("x-content-type-options", "nosniff")],
+        )
+        .case(
+            Sombrero::new_empty().referrer_policy(ReferrerPolicy::StrictOrigin),
+            [("referrer-policy", "strict-origin")],
+        )
+);
+
+#[test]
+fn fixture_run_all_visits_every_case() {
+    let fixture = crate::testing::SombreroFixture::standard();
+    let visited = std::cell::Cell::new(0);
+    fixture.run_all(|_, _| visited.set(visited.get() + 1));
+    assert_eq!(visited.get(), fixture.cases.len());
+    assert!(fixture.cases.len() >= 5);
+}
+
+#[test]
+fn prepared_sombrero_reserves_enabled_headers() {
+    let configs = [
+        Sombrero::default(),
+        Sombrero::new_empty(),
+        Sombrero::default()
+            .content_security_policy_report_only(ContentSecurityPolicy::locked_down())
+            .expose_csp_header(true)
+            .with_reporting("csp", "https://example.com/reports"),
+    ];
+    for sombrero in configs {
+        let mut expected = http::HeaderMap::new();
+        sombrero.insert_headers(&mut expected, "n0nce");
+
+        let prepared = crate::PreparedSombrero::new(sombrero);
+        let mut headers = http::HeaderMap::new();
+        prepared.insert_headers(&mut headers, "n0nce", &[]).unwrap();
+        assert_eq!(headers, expected);
+        assert_eq!(prepared.header_count, headers.len());
+        assert!(headers.capacity() >= prepared.header_count);
+    }
 }
 
 #[tokio::test]
-async fn sombrero_layer_one_nonce_per_request() {
+async fn headers_are_inserted_in_documented_order() {
+    let configs = [
+        Sombrero::default(),
+        Sombrero::default()
+            .content_security_policy_report_only(ContentSecurityPolicy::locked_down())
+            .expose_csp_header(true)
+            .with_strict_permissions_policy()
+            .with_reporting("csp", "https://example.com/reports")
+            .no_store(true),
+    ];
+    for sombrero in configs {
+        let order = sombrero.insert_order();
+        let response = apply_sombrero_oneshot(sombrero, http::Request::new(())).await;
+        let inserted: Vec<_> = response.headers().keys().cloned().collect();
+        assert_eq!(inserted, order);
+    }
+}
+
+#[cfg(feature = "axum-routing")]
+#[tokio::test]
+async fn axum_fn_middleware_matches_layer() {
+    use tower::ServiceExt;
+
+    let csp = ContentSecurityPolicy::strict_default().script_src([CspSource::Nonce]);
+    let sombrero = Sombrero::default().content_security_policy(csp);
+    let app = Router::new()
+        .route("/", get(test_handler))
+        .layer(axum::middleware::from_fn(
+            sombrero.clone().into_axum_fn_middleware(),
+        ));
+    let response = app
+        .oneshot(http::Request::new(axum::body::Body::empty()))
+        .await
+        .unwrap();
+    let expected = apply_sombrero_oneshot(sombrero, http::Request::new(())).await;
+
+    for (name, value) in expected.headers() {
+        if name == "content-security-policy" {
+            let csp = response.headers()[name].to_str().unwrap();
+            assert!(csp.contains("'nonce-"), "{csp}");
+        } else {
+            assert_eq!(response.headers().get(name), Some(value), "{name}");
+        }
+    }
+}
+
+#[test]
+fn sombrero_csp_security_level() {
+    use crate::headers::CspSecurityLevel;
+
+    assert_eq!(
+        Sombrero::default().csp_security_level(),
+        Some(CspSecurityLevel::Moderate)
+    );
+    assert_eq!(Sombrero::new_empty().csp_security_level(), None);
+}
+
+#[tokio::test]
+async fn report_only_everything_only_reports() {
+    let response =
+        apply_sombrero_oneshot(Sombrero::report_only_everything(), http::Request::new(())).await;
+    let headers = response.headers();
+    assert!(!headers.contains_key("content-security-policy"));
+    let default = apply_sombrero_oneshot(Sombrero::default(), http::Request::new(())).await;
+    assert_eq!(
+        headers["content-security-policy-report-only"],
+        default.headers()["content-security-policy"]
+    );
+    assert!(headers.contains_key("strict-transport-security"));
+
+    let reporting =
+        Sombrero::report_only_everything().with_reporting("csp", "https://example.com/r");
+    assert!(reporting.content_security_policy.is_none());
+    let report_only = reporting.content_security_policy_report_only.unwrap();
+    assert_eq!(report_only.report_to.as_deref(), Some("csp"));
+}
+
+#[cfg(feature = "body-hash-injection")]
+#[tokio::test]
+async fn hashing_csp_middleware_allows_inline_scripts() {
+    use axum::response::Html;
+    use tower::ServiceExt;
+
+    use crate::HashingCspMiddleware;
+
+    // sha256 of `alert(1)`
+    const HASH: &str = "'sha256-bhHHL3z2vDgxUt0W3dWQOrprscmda2Y5pLsLg4GF+pI='";
+
+    let app = Router::new()
+        .route(
+            "/",
+            get(|| async { Html("<html><body><script>alert(1)</script></body></html>") }),
+        )
+        .route("/text", get(|| async { "<script>alert(1)</script>" }))
+        .layer(HashingCspMiddleware::new(Sombrero::default()));
+
+    let request = |uri| {
+        http::Request::builder()
+            .uri(uri)
+            .body(axum::body::Body::empty())
+            .unwrap()
+    };
+    let response = app.clone().oneshot(request("/")).await.unwrap();
+    let csp = response.headers()["content-security-policy"]
+        .to_str()
+        .unwrap();
+    assert!(csp.contains(&format!("script-src 'self' {HASH};")), "{csp}");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(
+        &body[..],
+        b"<html><body><script>alert(1)</script></body></html>"
+    );
+
+    let response = app.oneshot(request("/text")).await.unwrap();
+    let csp = response.headers()["content-security-policy"]
+        .to_str()
+        .unwrap();
+    assert!(!csp.contains("sha256"), "{csp}");
+}
+
+#[tokio::test]
+async fn custom_csp_header_name_is_parsed_once() {
+    let sombrero = Sombrero::default()
+        .csp_header_name("x-proxied-csp")
+        .unwrap()
+        .expose_csp_header(true);
+    let cached = sombrero.csp_header_name.as_str().as_ptr();
+    for _ in 0..2 {
+        let response = apply_sombrero_oneshot(sombrero.clone(), http::Request::new(())).await;
+        let (name, _) = response
+            .headers()
+            .iter()
+            .find(|(name, _)| *name == "x-proxied-csp")
+            .unwrap();
+        // the same bytes as the configured name, not a freshly parsed copy
+        assert_eq!(name.as_str().as_ptr(), cached);
+        let exposed = &response.headers()["access-control-expose-headers"];
+        assert_eq!(exposed.as_bytes().as_ptr(), cached);
+    }
+}
+
+#[test]
+fn sombrero_pretty_and_debug_show_header_values() {
+    let pretty = format!(
+        "{}",
+        crate::SombreroPretty(&Sombrero::default(), "testnonce")
+    );
+    assert!(
+        pretty.contains("content-security-policy: default-src 'self';"),
+        "{pretty}"
+    );
+    assert!(pretty.contains("x-frame-options: SAMEORIGIN\n"), "{pretty}");
+    assert_eq!(pretty.lines().count(), 11);
+
     let csp = ContentSecurityPolicy::new_empty().script_src([CspSource::Nonce]);
+    let debug = format!("{:?}", Sombrero::new_empty().content_security_policy(csp));
+    assert!(
+        debug.contains("\"content-security-policy\": \"script-src 'nonce-NONCE';\""),
+        "{debug}"
+    );
+    assert!(debug.contains("script_src: [Nonce]"), "{debug}");
+}
+
+#[test]
+#[allow(deprecated)]
+fn sombrero_debug_shows_invalid_values_inline() {
+    let expect_ct = crate::headers::ExpectCt {
+        max_age: 0,
+        enforce: false,
+        report_uri: Some("https://example.com/\n".to_string()),
+    };
+    let csp = ContentSecurityPolicy::new_empty().report_uri("not a uri");
     let sombrero = Sombrero::new_empty()
-        .content_security_policy(csp.clone())
-        .content_security_policy_report_only(csp);
-    let server = test_server(sombrero).await;
+        .content_security_policy(csp)
+        .expect_ct(expect_ct);
+    let debug = format!("{sombrero:?}");
+    assert!(
+        debug.contains("\"content-security-policy\": <invalid: "),
+        "{debug}"
+    );
+    assert!(debug.contains("\"expect-ct\": <invalid: "), "{debug}");
+}
+
+#[tokio::test]
+async fn nonce_seed_shared_between_csp_layers() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    use crate::csp::{CspNonce, NonceSeedLayer};
+
+    let csp = ContentSecurityPolicy::new_empty().script_src([CspSource::Nonce]);
+    let inner = service_fn(|request: http::Request<()>| async move {
+        let nonce = request.extensions().get::<CspNonce>().unwrap();
+        let response = http::Response::builder()
+            .header("x-nonce", nonce.as_str())
+            .body(())
+            .unwrap();
+        Ok::<_, std::convert::Infallible>(response)
+    });
+    let stack = |seeded: bool| {
+        let csps =
+            CspLayer::new(csp.clone()).layer(CspLayer::new_report_only(csp.clone()).layer(inner));
+        tower::util::option_layer(seeded.then_some(NonceSeedLayer)).layer(csps)
+    };
+
+    let response = stack(true).oneshot(http::Request::new(())).await.unwrap();
+    let headers = response.headers();
+    let expected = format!(
+        "script-src 'nonce-{}';",
+        headers["x-nonce"].to_str().unwrap()
+    );
+    assert_eq!(headers["content-security-policy"], expected);
+    assert_eq!(headers["content-security-policy-report-only"], expected);
+
+    let response = stack(false).oneshot(http::Request::new(())).await.unwrap();
+    let headers = response.headers();
+    assert_ne!(
+        headers["content-security-policy"],
+        headers["content-security-policy-report-only"]
+    );
+}
+
+#[tokio::test]
+async fn sombrero_uses_nonce_seed() {
+    use crate::csp::NonceSeed;
+
+    let csp = ContentSecurityPolicy::new_empty().script_src([CspSource::Nonce]);
+    let seed = NonceSeed([7; 24]);
+    let mut request = http::Request::new(());
+    request.extensions_mut().insert(seed);
+    let sombrero = Sombrero::new_empty().content_security_policy(csp);
+    let response = apply_sombrero_oneshot(sombrero, request).await;
+    assert_eq!(
+        response.headers()["content-security-policy"],
+        format!("script-src 'nonce-{}';", seed.nonce())
+    );
+    assert_eq!(format!("{seed:?}"), "NonceSeed(..)");
+}
+
+#[tokio::test]
+async fn modifier_nonce_uses_nonce_seed() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    use crate::{csp::NonceSeed, SombreroModifier};
+
+    let inner = service_fn(|request: http::Request<()>| async move {
+        let modifier = request.extensions().get::<SombreroModifier>().unwrap();
+        modifier.modify(|sombrero| {
+            sombrero.content_security_policy(
+                ContentSecurityPolicy::new_empty().script_src([CspSource::Nonce]),
+            )
+        });
+        Ok::<_, std::convert::Infallible>(http::Response::new(()))
+    });
+    let seed = NonceSeed([3; 24]);
+    let mut request = http::Request::new(());
+    request.extensions_mut().insert(seed);
+    let response = Sombrero::new_empty()
+        .layer(inner)
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers()["content-security-policy"],
+        format!("script-src 'nonce-{}';", seed.nonce())
+    );
+}
+
+#[tokio::test]
+async fn reloadable_sombrero_applies_updates() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    use crate::{headers::XFrameOptions, ReloadableSombrero};
+
+    let (layer, handle) = ReloadableSombrero::new(Sombrero::default()).split();
+    let service = layer.layer(service_fn(|_: http::Request<()>| async {
+        tokio::task::yield_now().await;
+        Ok::<_, std::convert::Infallible>(http::Response::new(()))
+    }));
+    let batch = |service: crate::ReloadableSombreroService<_>| async move {
+        let requests = (0..8).map(|_| {
+            let service = service.clone();
+            tokio::spawn(async move {
+                let response = service.oneshot(http::Request::new(())).await.unwrap();
+                response.headers()["x-frame-options"].clone()
+            })
+        });
+        futures_util::future::join_all(requests)
+            .await
+            .into_iter()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+    };
+
+    assert!(batch(service.clone())
+        .await
+        .iter()
+        .all(|value| value == "SAMEORIGIN"));
+    let updater = tokio::spawn(async move {
+        handle.update(Sombrero::default().x_frame_options(XFrameOptions::Deny));
+        handle
+    });
+    let handle = updater.await.unwrap();
+    assert_eq!(handle.get().x_frame_options, Some(XFrameOptions::Deny));
+    assert!(batch(service).await.iter().all(|value| value == "DENY"));
+}
+
+/// Not ready on the first poll of each round, and panics if called without being ready.
+#[derive(Clone, Default)]
+struct PendingOnceService {
+    polls: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    ready: bool,
+}
+
+impl tower::Service<http::Request<()>> for PendingOnceService {
+    type Error = std::convert::Infallible;
+    type Future = std::future::Ready<Result<http::Response<()>, Self::Error>>;
+    type Response = http::Response<()>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        use std::sync::atomic::Ordering;
+
+        if self.polls.fetch_add(1, Ordering::SeqCst).is_multiple_of(2) {
+            cx.waker().wake_by_ref();
+            return std::task::Poll::Pending;
+        }
+        self.ready = true;
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _: http::Request<()>) -> Self::Future {
+        assert!(self.ready, "called before poll_ready returned Ready");
+        self.ready = false;
+        std::future::ready(Ok(http::Response::new(())))
+    }
+}
+
+async fn assert_poll_ready_delegated<S>(mut service: S, polls: &std::sync::atomic::AtomicUsize)
+where
+    S: tower::Service<http::Request<()>, Response = http::Response<()>> + Send,
+    S::Future: Send,
+    S::Error: std::fmt::Debug,
+{
+    use std::{
+        sync::atomic::Ordering,
+        task::{Context, Poll},
+    };
+
+    {
+        let mut cx = Context::from_waker(futures_util::task::noop_waker_ref());
+        assert!(service.poll_ready(&mut cx).is_pending());
+        assert_eq!(polls.load(Ordering::SeqCst), 1);
+        assert!(matches!(service.poll_ready(&mut cx), Poll::Ready(Ok(()))));
+        assert_eq!(polls.load(Ordering::SeqCst), 2);
+    }
+    let response = service.call(http::Request::new(())).await.unwrap();
+    assert!(response.headers().contains_key("content-security-policy"));
+
+    // `ServiceExt::ready` must wait through the next `Pending` as well
+    let response = tower::ServiceExt::oneshot(service, http::Request::new(()))
+        .await
+        .unwrap();
+    assert!(response.headers().contains_key("content-security-policy"));
+    assert_eq!(polls.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test]
+async fn sombrero_service_delegates_poll_ready() {
+    use tower::Layer;
+
+    let inner = PendingOnceService::default();
+    let polls = inner.polls.clone();
+    assert_poll_ready_delegated(Sombrero::default().layer(inner), &polls).await;
+}
+
+#[tokio::test]
+async fn csp_service_delegates_poll_ready() {
+    use tower::Layer;
+
+    let inner = PendingOnceService::default();
+    let polls = inner.polls.clone();
+    let layer = CspLayer::new(ContentSecurityPolicy::strict_default());
+    assert_poll_ready_delegated(layer.layer(inner), &polls).await;
+}
+
+#[test]
+fn sombrero_clones_share_policies() {
+    use std::sync::Arc;
+
+    use crate::csp::SequentialNonce;
+
+    let sombrero = Sombrero::default()
+        .content_security_policy_report_only(ContentSecurityPolicy::strict_default())
+        .nonce_generator(SequentialNonce::new("n"));
+    let clone = sombrero.clone();
+    let shared = |s: &Sombrero| {
+        (
+            s.content_security_policy.clone().unwrap(),
+            s.content_security_policy_report_only.clone().unwrap(),
+            s.nonce_generator.clone().unwrap(),
+        )
+    };
+    let (csp, report_only, generator) = shared(&sombrero);
+    let (clone_csp, clone_report_only, clone_generator) = shared(&clone);
+    assert!(Arc::ptr_eq(&csp, &clone_csp));
+    assert!(Arc::ptr_eq(&report_only, &clone_report_only));
+    assert!(Arc::ptr_eq(&generator, &clone_generator));
+    assert_eq!(format!("{sombrero:?}"), format!("{clone:?}"));
+
+    let edited = clone.edit_csp(|csp| csp.deny_object_src());
+    assert!(!Arc::ptr_eq(
+        &csp,
+        edited.content_security_policy.as_ref().unwrap()
+    ));
+
+    let shared = sombrero.share();
+    let other = Arc::clone(&shared);
+    assert!(Arc::ptr_eq(&shared, &other));
+    assert!(Arc::ptr_eq(
+        shared.content_security_policy.as_ref().unwrap(),
+        &csp
+    ));
+}
+
+#[test]
+fn sombrero_static_initializes_once() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use tower::Layer;
+
+    static INITS: AtomicUsize = AtomicUsize::new(0);
+    crate::lazy_sombrero!(
+        SOMBRERO = || {
+            INITS.fetch_add(1, Ordering::SeqCst);
+            Sombrero::default().no_store(true)
+        }
+    );
+    crate::lazy_sombrero!(DEFAULT);
+
+    assert_eq!(INITS.load(Ordering::SeqCst), 0);
+    let first = SOMBRERO.layer(());
+    fn by_ref<L: Layer<()>>(layer: L) -> L::Service {
+        layer.layer(())
+    }
+    let second = by_ref(&SOMBRERO);
+    let threads: Vec<_> = (0..4)
+        .map(|_| std::thread::spawn(|| SOMBRERO.layer(()).prepared))
+        .collect();
+    for thread in threads {
+        assert!(Arc::ptr_eq(&thread.join().unwrap(), &first.prepared));
+    }
+    assert!(Arc::ptr_eq(&first.prepared, &second.prepared));
+    assert!(SOMBRERO.get().no_store);
+    assert_eq!(INITS.load(Ordering::SeqCst), 1);
+    assert!(!DEFAULT.get().no_store);
+}
+
+#[tokio::test]
+async fn sombrero_into_static_layer() {
+    let sombrero: &'static Sombrero = Sombrero::default().into_static();
+    let app = Router::new().route("/", get(test_handler)).layer(sombrero);
+    let server = test_server_router(app).await;
     let resp = reqwest::get(server.url()).await.unwrap();
-    let nonce_ac = helper_get_nonce(&resp, "content-security-policy");
-    let nonce_ro = helper_get_nonce(&resp, "content-security-policy-report-only");
-    assert_eq!(nonce_ac, nonce_ro);
+    assert!(resp.headers().get("content-security-policy").is_some());
     server.shutdown().await;
 }
 
-fn helper_get_nonce(resp: &reqwest::Response, name: &str) -> String {
-    resp.headers()
-        .get(name)
+#[test]
+fn apply_headers_deterministic_uses_given_bytes() {
+    use crate::RANDOM_STRINGS_GENERATED;
+
+    let csp = ContentSecurityPolicy::strict_default().script_src([CspSource::Nonce]);
+    let sombrero = Sombrero::default().content_security_policy(csp);
+    let apply = |nonce: &[u8]| {
+        let mut response = http::Response::new(());
+        sombrero.apply_headers_deterministic(&mut response, nonce);
+        response.headers().clone()
+    };
+    let before = RANDOM_STRINGS_GENERATED.get();
+    let first = apply(b"\x00\x01\x02\xfe\xff");
+    assert_eq!(first, apply(b"\x00\x01\x02\xfe\xff"));
+    assert_ne!(first, apply(b"other"));
+    assert_eq!(RANDOM_STRINGS_GENERATED.get(), before);
+    let csp = first["content-security-policy"].to_str().unwrap();
+    assert!(csp.contains("script-src 'nonce-AAEC/v8=';"), "{csp}");
+}
+
+#[test]
+fn describe_explains_headers() {
+    use crate::headers::{XDnsPrefetchControl, XXssProtection};
+
+    let rationale = |sombrero: &Sombrero, name: &str| {
+        sombrero
+            .describe("n0nce")
+            .into_iter()
+            .find(|(header, _, _)| header == name)
+            .map(|(_, _, rationale)| rationale)
+    };
+
+    let helmet = Sombrero::helmet_compatible();
+    let described = helmet.describe("n0nce");
+    assert!(described
+        .iter()
+        .all(|(_, _, rationale)| !rationale.is_empty()));
+    let xss = rationale(&helmet, "x-xss-protection").unwrap();
+    assert!(xss.contains("buggy"), "{xss}");
+    assert!(xss.starts_with("Turns off"), "{xss}");
+    let dns = rationale(&helmet, "x-dns-prefetch-control").unwrap();
+    assert!(dns.contains("slower"), "{dns}");
+    assert!(dns.starts_with("Stops"), "{dns}");
+
+    let nonced = Sombrero::new_empty()
+        .content_security_policy(ContentSecurityPolicy::new_empty().script_src([CspSource::Nonce]))
+        .no_cache_with_nonce();
+    let cache = rationale(&nonced, "cache-control").unwrap();
+    assert!(cache.contains("nonce"), "{cache}");
+    let streaming = Sombrero::new_empty().no_store(true);
+    let cache = rationale(&streaming, "cache-control").unwrap();
+    assert!(!cache.contains("nonce"), "{cache}");
+
+    let loose = Sombrero::default()
+        .x_dns_prefetch_control(XDnsPrefetchControl::On)
+        .x_xss_protection(XXssProtection::TrueBlock);
+    assert!(rationale(&loose, "x-dns-prefetch-control")
         .unwrap()
-        .to_str()
+        .contains("faster"));
+    assert!(rationale(&loose, "x-xss-protection")
         .unwrap()
-        .trim_start_matches("script-src 'nonce-")
-        .trim_end_matches("';")
-        .to_string()
+        .contains("`0` is safer"));
+    assert_eq!(
+        rationale(&Sombrero::default(), "x-dns-prefetch-control"),
+        None
+    );
+
+    let renamed = Sombrero::default().csp_header_name("x-csp").unwrap();
+    assert!(rationale(&renamed, "x-csp")
+        .unwrap()
+        .contains("cross-site scripting"));
+}
+
+struct TestError;
+
+impl From<TestError> for http::Response<()> {
+    fn from(_: TestError) -> Self {
+        let mut response = Self::new(());
+        *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        response
+    }
+}
+
+#[tokio::test]
+async fn fail_open_adds_headers_to_errors() {
+    use tower::{service_fn, Layer, ServiceExt};
+
+    let failing =
+        service_fn(|_: http::Request<()>| async { Err::<http::Response<()>, _>(TestError) });
+    let response = Sombrero::default()
+        .fail_open()
+        .layer(failing)
+        .oneshot(http::Request::new(()))
+        .await
+        .unwrap_or_else(|_| panic!("errors should become responses"));
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(response.headers().contains_key("content-security-policy"));
+    assert!(response.headers().contains_key("x-content-type-options"));
 }
 
 async fn test_server(sombrero: Sombrero) -> Server {
+    test_server_router(Router::new().route("/", get(test_handler)).layer(sombrero)).await
+}
+
+async fn test_server_router(app: Router) -> Server {
     let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
-    let app = Router::new().route("/", get(test_handler)).layer(sombrero);
     let port = listener.local_addr().unwrap().port();
     let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
     let task = tokio::spawn(async {
@@ -124,3 +2508,11 @@ impl Server {
 async fn test_handler() -> &'static str {
     "Test Handler!"
 }
+
+async fn rate_limited_handler() -> (StatusCode, [(&'static str, &'static str); 1], &'static str) {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [("retry-after", "10")],
+        "Slow down!",
+    )
+}